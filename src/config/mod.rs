@@ -0,0 +1,104 @@
+use std::env;
+
+/// Settings that come from the command line, and for proxies the environment
+///
+/// usage: tundra [--ca-file <pem>] [--max-redirects <n>] [--cache-dir <dir>] [--cookie-file <file>]
+///        [--connect-timeout <secs>] [--read-timeout <secs>] [--timeout <secs>]
+///        [--proxy <url>] [--no-proxy <hosts>] [<url>]
+///
+/// Without a url Tundra starts on about:blank. A timeout of 0 means no limit.
+/// Without --proxy, the http_proxy, https_proxy and no_proxy environment variables are used
+#[derive(Clone)]
+pub struct Config {
+    pub url: String,
+    /// Extra root certificates to trust for https, in PEM format
+    pub ca_file: Option<String>,
+    /// How many redirects to follow before giving up on a page
+    pub max_redirects: usize,
+    /// Where to keep cached responses between runs. Without it the cache is memory only
+    pub cache_dir: Option<String>,
+    /// Where to keep cookies between runs. Without it they only last the session
+    pub cookie_file: Option<String>,
+    /// Seconds to wait for a server to accept a connection
+    pub connect_timeout: usize,
+    /// Seconds a server can go quiet in the middle of a response
+    pub read_timeout: usize,
+    /// Seconds a whole page load can take, redirects included
+    pub timeout: usize,
+    /// The proxy for http:// urls, as http://host:port, socks5://host:port or socks5h://host:port
+    pub http_proxy: Option<String>,
+    /// The proxy for https:// urls
+    pub https_proxy: Option<String>,
+    /// Comma separated hosts to connect to directly, even with a proxy set
+    pub no_proxy: Option<String>,
+}
+
+impl Config {
+    pub fn from_args(args: Vec<String>) -> Config {
+        let mut url = None;
+        let mut ca_file = None;
+        let mut max_redirects = 10;
+        let mut cache_dir = None;
+        let mut cookie_file = None;
+        let mut connect_timeout = 10;
+        let mut read_timeout = 30;
+        let mut timeout = 120;
+        let mut proxy = None;
+        let mut no_proxy = None;
+
+        let mut args = args.into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ca-file" => ca_file = Some(flag_value(&arg, args.next())),
+                "--max-redirects" => max_redirects = number_value(&arg, args.next()),
+                "--cache-dir" => cache_dir = Some(flag_value(&arg, args.next())),
+                "--cookie-file" => cookie_file = Some(flag_value(&arg, args.next())),
+                "--connect-timeout" => connect_timeout = number_value(&arg, args.next()),
+                "--read-timeout" => read_timeout = number_value(&arg, args.next()),
+                "--timeout" => timeout = number_value(&arg, args.next()),
+                "--proxy" => proxy = Some(flag_value(&arg, args.next())),
+                "--no-proxy" => no_proxy = Some(flag_value(&arg, args.next())),
+                _ => {
+                    if url.is_some() {
+                        panic!("Must provide one and only one url");
+                    }
+                    url = Some(arg);
+                }
+            }
+        }
+
+        Config {
+            url: url.unwrap_or_else(|| "about:blank".to_string()),
+            ca_file,
+            max_redirects,
+            cache_dir,
+            cookie_file,
+            connect_timeout,
+            read_timeout,
+            timeout,
+            http_proxy: proxy.clone().or_else(|| env_value("http_proxy")),
+            https_proxy: proxy.or_else(|| env_value("https_proxy")),
+            no_proxy: no_proxy.or_else(|| env_value("no_proxy")),
+        }
+    }
+}
+
+/// An environment variable, in lower or upper case, if it's set to something
+fn env_value(name: &str) -> Option<String> {
+    env::var(name).or_else(|_| env::var(name.to_uppercase())).ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn flag_value(flag: &str, value: Option<String>) -> String {
+    match value {
+        Some(value) => value,
+        None => panic!("{} needs a value", flag),
+    }
+}
+
+fn number_value(flag: &str, value: Option<String>) -> usize {
+    match flag_value(flag, value).parse() {
+        Ok(n) => n,
+        Err(_) => panic!("{} needs a number", flag),
+    }
+}
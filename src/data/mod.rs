@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use data_url::DataUrl;
+use url::Url;
+
+use crate::http::{FetchError, Response};
+
+/// Decodes a data: url into a response, without going near the network.
+///
+/// data_url follows the fetch standard here: the payload is percent-decoded, and
+/// base64-decoded too if the media type ends in ;base64. A missing media type means
+/// text/plain;charset=US-ASCII
+pub fn load(url: &Url) -> Result<Response, FetchError> {
+    let bad_url = || FetchError::BadUrl(url.to_string());
+    let data_url = DataUrl::process(url.as_str()).map_err(|_| bad_url())?;
+    // any #fragment comes back separately, it isn't part of the document
+    let (body, _fragment) = data_url.decode_to_vec().map_err(|_| bad_url())?;
+
+    let mime_type = data_url.mime_type();
+    let mut content_type = format!("{}/{}", mime_type.type_, mime_type.subtype);
+    for (name, value) in mime_type.parameters.iter() {
+        content_type.push_str(&format!(";{}={}", name, value));
+    }
+
+    let mut headers = HashMap::new();
+    headers.insert("content-type".to_string(), content_type);
+    Ok(Response { status: 200, reason: "OK".to_string(), headers, body, persistent: false })
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use url::Url;
+
+use crate::escape_html;
+use crate::http::{FetchError, Response};
+
+/// Opens a file:// url. Files come back as a response with a Content-Type guessed from
+/// the extension, and directories as a generated listing page.
+///
+/// Like a web server would, a directory url without a trailing slash gets redirected to
+/// one with it, so that relative links from the listing land inside the directory
+pub fn load(url: &Url) -> Result<Response, FetchError> {
+    let path = match url.to_file_path() {
+        Ok(path) => path,
+        Err(_) => return Err(FetchError::BadUrl(url.to_string())),
+    };
+    let metadata = fs::metadata(&path).map_err(|e| FetchError::File(path.display().to_string(), e))?;
+
+    let mut headers = HashMap::new();
+    if metadata.is_dir() {
+        if !url.path().ends_with('/') {
+            let mut location = url.clone();
+            location.set_path(&format!("{}/", url.path()));
+            headers.insert("location".to_string(), location.to_string());
+            return Ok(response(301, headers, Vec::new()));
+        }
+        let listing = directory_listing(&path)?;
+        headers.insert("content-type".to_string(), "text/html; charset=utf-8".to_string());
+        return Ok(response(200, headers, listing.into_bytes()));
+    }
+
+    let body = fs::read(&path).map_err(|e| FetchError::File(path.display().to_string(), e))?;
+    headers.insert("content-type".to_string(), content_type(&path).to_string());
+    Ok(response(200, headers, body))
+}
+
+fn response(status: u16, headers: HashMap<String, String>, body: Vec<u8>) -> Response {
+    let reason = if status == 200 { "OK" } else { "Moved Permanently" };
+    Response { status, reason: reason.to_string(), headers, body, persistent: false }
+}
+
+/// Guesses a media type from the file extension, for the handful of types a browser
+/// is likely to be pointed at
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" | "xhtml" => "text/html",
+        "txt" | "text" | "md" | "rs" | "py" | "toml" => "text/plain",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Builds an index page for a directory, folders first and then files, alphabetically
+fn directory_listing(path: &Path) -> Result<String, FetchError> {
+    let entries = fs::read_dir(path).map_err(|e| FetchError::File(path.display().to_string(), e))?;
+    let mut names: Vec<(bool, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            (!is_dir, entry.file_name().to_string_lossy().to_string())
+        })
+        .collect();
+    names.sort();
+
+    let title = escape_html(&path.display().to_string());
+    let mut html = format!("<html><head><title>Index of {}</title></head><body><h1>Index of {}</h1>",
+                           title, title);
+    if path.parent().is_some() {
+        html.push_str("<p><a href=\"../\">../</a></p>");
+    }
+    for (is_file, name) in names {
+        let slash = if is_file { "" } else { "/" };
+        // the href is a url, so the name has to be percent-encoded rather than escaped. The
+        // ./ stops a name like notes:draft.html being taken for a url with a notes: scheme
+        let mut href = Url::parse("file:///").unwrap();
+        href.set_path(&name);
+        let href = &href.path()[1..];
+        html.push_str(&format!("<p><a href=\"./{}{}\">{}{}</a></p>", escape_html(href), slash, escape_html(&name), slash));
+    }
+    html.push_str("</body></html>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn listing_links_stay_in_the_directory() {
+        let dir = env::temp_dir().join(format!("tundra-listing-{}", process::id()));
+        fs::create_dir_all(dir.join("sub dir")).unwrap();
+        fs::write(dir.join("notes:draft.html"), "").unwrap();
+        let listing = directory_listing(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let listing = listing.unwrap();
+
+        let base = Url::from_directory_path(&dir).unwrap();
+        let hrefs: Vec<Url> = listing.split("href=\"").skip(1)
+            .map(|rest| base.join(&rest[..rest.find('"').unwrap()]).unwrap())
+            .collect();
+        assert_eq!(hrefs, vec![
+            base.join("..").unwrap(),
+            base.join("sub%20dir/").unwrap(),
+            Url::from_file_path(dir.join("notes:draft.html")).unwrap(),
+        ]);
+    }
+}
@@ -0,0 +1,4 @@
+mod entities;
+mod tokenizer;
+
+pub use self::tokenizer::{tokenize, Token};
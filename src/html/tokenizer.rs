@@ -0,0 +1,684 @@
+use super::entities::{LONGEST_NAME, NAMED_REFERENCES};
+
+/// One piece of an HTML document, as the tokenizer hands them to the parser
+#[derive(Debug, PartialEq)]
+pub enum Token {
+    Text(String),
+    /// Tag and attribute names are lowercased, attribute values have their character
+    /// references decoded and any quotes taken off. An attribute with no value has an
+    /// empty one, and if a name comes up twice only the first is kept
+    StartTag {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    EndTag {
+        name: String,
+    },
+    Comment(String),
+    /// Whatever came after <!DOCTYPE
+    Doctype(String),
+}
+
+/// The tokenizer states from section 13.2.5 of the HTML standard that we need. Character
+/// references don't get a state of their own here, consume_character_reference() does
+/// the whole thing in one go and returns to whichever state it was called from
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Data,
+    /// Inside <textarea> or <title>: no tags, but character references still count
+    RcData,
+    /// Inside <script> or <style>: nothing but text until the matching end tag
+    RawText,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    MarkupDeclarationOpen,
+    BogusComment,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+}
+
+/// The tag being built up, until its > comes along
+#[derive(Default)]
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    self_closing: bool,
+    end: bool,
+}
+
+/// Splits an HTML document into tokens, the way a browser does: a stray < or > is just
+/// text, quoted attribute values can hold anything but their quote, and comments run
+/// to the first --> whatever's inside them.
+///
+/// The contents of <script> and <style>, and of <textarea> and <title> after their
+/// character references are decoded, come out as text however much they look like
+/// markup. In a browser the tree builder tells the tokenizer when to do that, here the
+/// tokenizer goes by the name of the start tag it just emitted.
+///
+/// Malformed markup is recovered from the way the standard says to, rather than with
+/// an error, so every input gives some tokens
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokenizer = Tokenizer {
+        input: source.chars().collect(),
+        pos: 0,
+        state: State::Data,
+        tokens: Vec::new(),
+        text: String::new(),
+        tag: Tag::default(),
+        comment: String::new(),
+        last_start_tag: String::new(),
+    };
+    tokenizer.run();
+    tokenizer.tokens
+}
+
+struct Tokenizer {
+    input: Vec<char>,
+    pos: usize,
+    state: State,
+    tokens: Vec<Token>,
+    /// Text waiting to be emitted as a single token
+    text: String,
+    tag: Tag,
+    /// Also used for the doctype
+    comment: String,
+    /// The name of the last start tag emitted, which is the only end tag that gets
+    /// out of RCDATA or RAWTEXT
+    last_start_tag: String,
+}
+
+impl Tokenizer {
+    fn run(&mut self) {
+        loop {
+            let c = self.input.get(self.pos).copied();
+            self.pos += 1;
+            match self.state {
+                State::Data => match c {
+                    Some('&') => {
+                        let decoded = self.consume_character_reference(false);
+                        self.text.push_str(&decoded);
+                    },
+                    Some('<') => self.state = State::TagOpen,
+                    Some(c) => self.text.push(c),
+                    None => break,
+                },
+                State::RcData | State::RawText => match c {
+                    Some('&') if self.state == State::RcData => {
+                        let decoded = self.consume_character_reference(false);
+                        self.text.push_str(&decoded);
+                    },
+                    Some('<') if self.at_appropriate_end_tag() => {
+                        self.pos += 1;
+                        self.state = State::EndTagOpen;
+                    },
+                    Some(c) => self.text.push(c),
+                    None => break,
+                },
+                State::TagOpen => match c {
+                    Some('!') => self.state = State::MarkupDeclarationOpen,
+                    Some('/') => self.state = State::EndTagOpen,
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.tag = Tag::default();
+                        self.reconsume(State::TagName);
+                    },
+                    Some('?') => {
+                        self.comment.clear();
+                        self.reconsume(State::BogusComment);
+                    },
+                    // not a tag after all, so the < was just text
+                    _ => {
+                        self.text.push('<');
+                        self.reconsume(State::Data);
+                    },
+                },
+                State::EndTagOpen => match c {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.tag = Tag { end: true, ..Tag::default() };
+                        self.reconsume(State::TagName);
+                    },
+                    // </> is dropped altogether
+                    Some('>') => self.state = State::Data,
+                    Some(_) => {
+                        self.comment.clear();
+                        self.reconsume(State::BogusComment);
+                    },
+                    None => {
+                        self.text.push_str("</");
+                        break;
+                    },
+                },
+                State::TagName => match c {
+                    Some(c) if is_whitespace(c) => self.state = State::BeforeAttributeName,
+                    Some('/') => self.state = State::SelfClosingStartTag,
+                    Some('>') => self.emit_tag(),
+                    Some(c) => self.tag.name.push(lowercase(c)),
+                    // a tag cut off by the end of the file is dropped
+                    None => break,
+                },
+                State::BeforeAttributeName => match c {
+                    Some(c) if is_whitespace(c) => (),
+                    Some('/') | Some('>') | None => self.reconsume(State::AfterAttributeName),
+                    Some('=') => {
+                        self.tag.attributes.push(("=".to_string(), String::new()));
+                        self.state = State::AttributeName;
+                    },
+                    Some(_) => {
+                        self.tag.attributes.push((String::new(), String::new()));
+                        self.reconsume(State::AttributeName);
+                    },
+                },
+                State::AttributeName => match c {
+                    Some(c) if is_whitespace(c) => self.reconsume(State::AfterAttributeName),
+                    Some('/') | Some('>') | None => self.reconsume(State::AfterAttributeName),
+                    Some('=') => self.state = State::BeforeAttributeValue,
+                    Some(c) => self.attribute().0.push(lowercase(c)),
+                },
+                State::AfterAttributeName => match c {
+                    Some(c) if is_whitespace(c) => (),
+                    Some('/') => self.state = State::SelfClosingStartTag,
+                    Some('=') => self.state = State::BeforeAttributeValue,
+                    Some('>') => self.emit_tag(),
+                    Some(_) => {
+                        self.tag.attributes.push((String::new(), String::new()));
+                        self.reconsume(State::AttributeName);
+                    },
+                    None => break,
+                },
+                State::BeforeAttributeValue => match c {
+                    Some(c) if is_whitespace(c) => (),
+                    Some('"') => self.state = State::AttributeValueDoubleQuoted,
+                    Some('\'') => self.state = State::AttributeValueSingleQuoted,
+                    // name= with no value
+                    Some('>') => self.emit_tag(),
+                    _ => self.reconsume(State::AttributeValueUnquoted),
+                },
+                State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => {
+                    let quote = if self.state == State::AttributeValueDoubleQuoted { '"' } else { '\'' };
+                    match c {
+                        Some(c) if c == quote => self.state = State::AfterAttributeValueQuoted,
+                        Some('&') => {
+                            let decoded = self.consume_character_reference(true);
+                            self.attribute().1.push_str(&decoded);
+                        },
+                        Some(c) => self.attribute().1.push(c),
+                        None => break,
+                    }
+                },
+                State::AttributeValueUnquoted => match c {
+                    Some(c) if is_whitespace(c) => self.state = State::BeforeAttributeName,
+                    Some('&') => {
+                        let decoded = self.consume_character_reference(true);
+                        self.attribute().1.push_str(&decoded);
+                    },
+                    Some('>') => self.emit_tag(),
+                    Some(c) => self.attribute().1.push(c),
+                    None => break,
+                },
+                State::AfterAttributeValueQuoted => match c {
+                    Some(c) if is_whitespace(c) => self.state = State::BeforeAttributeName,
+                    Some('/') => self.state = State::SelfClosingStartTag,
+                    Some('>') => self.emit_tag(),
+                    Some(_) => self.reconsume(State::BeforeAttributeName),
+                    None => break,
+                },
+                State::SelfClosingStartTag => match c {
+                    Some('>') => {
+                        self.tag.self_closing = true;
+                        self.emit_tag();
+                    },
+                    Some(_) => self.reconsume(State::BeforeAttributeName),
+                    None => break,
+                },
+                State::MarkupDeclarationOpen => {
+                    // we've already taken the character after the !, so look from there
+                    self.pos -= 1;
+                    self.comment.clear();
+                    if self.lookahead("--", false) {
+                        self.pos += 2;
+                        self.state = State::CommentStart;
+                    } else if self.lookahead("doctype", true) {
+                        self.pos += 7;
+                        self.state = State::Doctype;
+                    } else {
+                        // <![CDATA[ is only a thing inside SVG and MathML, which we don't do
+                        self.state = State::BogusComment;
+                    }
+                },
+                State::BogusComment => match c {
+                    Some('>') => self.emit_comment(),
+                    Some(c) => self.comment.push(c),
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                State::CommentStart => match c {
+                    Some('-') => self.state = State::CommentStartDash,
+                    // <!--> is an empty comment
+                    Some('>') => self.emit_comment(),
+                    _ => self.reconsume(State::Comment),
+                },
+                State::CommentStartDash => match c {
+                    Some('-') => self.state = State::CommentEnd,
+                    Some('>') => self.emit_comment(),
+                    Some(_) => {
+                        self.comment.push('-');
+                        self.reconsume(State::Comment);
+                    },
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                State::Comment => match c {
+                    Some('-') => self.state = State::CommentEndDash,
+                    Some(c) => self.comment.push(c),
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                State::CommentEndDash => match c {
+                    Some('-') => self.state = State::CommentEnd,
+                    Some(_) => {
+                        self.comment.push('-');
+                        self.reconsume(State::Comment);
+                    },
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                State::CommentEnd => match c {
+                    Some('>') => self.emit_comment(),
+                    Some('!') => self.state = State::CommentEndBang,
+                    Some('-') => self.comment.push('-'),
+                    Some(_) => {
+                        self.comment.push_str("--");
+                        self.reconsume(State::Comment);
+                    },
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                State::CommentEndBang => match c {
+                    Some('-') => {
+                        self.comment.push_str("--!");
+                        self.state = State::CommentEndDash;
+                    },
+                    Some('>') => self.emit_comment(),
+                    Some(_) => {
+                        self.comment.push_str("--!");
+                        self.reconsume(State::Comment);
+                    },
+                    None => {
+                        self.emit_comment();
+                        break;
+                    },
+                },
+                // The standard picks the doctype apart into a name and public and system
+                // ids, which only matter for quirks mode. We keep it as it came
+                State::Doctype => match c {
+                    Some('>') | None => {
+                        self.flush_text();
+                        let doctype = self.comment.trim().to_string();
+                        self.tokens.push(Token::Doctype(doctype));
+                        self.state = State::Data;
+                        if c.is_none() {
+                            break;
+                        }
+                    },
+                    Some(c) => self.comment.push(c),
+                },
+            }
+        }
+        self.flush_text();
+    }
+
+    /// Goes to another state without using up the current character
+    fn reconsume(&mut self, state: State) {
+        self.pos -= 1;
+        self.state = state;
+    }
+
+    /// Whether the input carries on with `expected`
+    fn lookahead(&self, expected: &str, ignore_case: bool) -> bool {
+        let mut pos = self.pos;
+        for expected in expected.chars() {
+            match self.input.get(pos) {
+                Some(c) if *c == expected || (ignore_case && c.eq_ignore_ascii_case(&expected)) => pos += 1,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether the input carries on with the end tag for the element whose text we're
+    /// in, so that </scripts> or a </style> inside a <script> don't end it
+    fn at_appropriate_end_tag(&self) -> bool {
+        let end_tag = format!("/{}", self.last_start_tag);
+        if !self.lookahead(&end_tag, true) {
+            return false;
+        }
+        match self.input.get(self.pos + end_tag.chars().count()) {
+            Some(&c) => is_whitespace(c) || c == '/' || c == '>',
+            None => false,
+        }
+    }
+
+    /// The attribute being built up, which is always the last one in the tag
+    fn attribute(&mut self) -> &mut (String, String) {
+        self.tag.attributes.last_mut().unwrap()
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.tokens.push(Token::Text(text));
+        }
+    }
+
+    fn emit_tag(&mut self) {
+        self.flush_text();
+        let mut tag = std::mem::take(&mut self.tag);
+        let mut seen = Vec::new();
+        tag.attributes.retain(|(name, _)| {
+            let first = !seen.contains(name);
+            seen.push(name.clone());
+            first
+        });
+        self.state = State::Data;
+        let token = if tag.end {
+            Token::EndTag { name: tag.name }
+        } else {
+            // a browser would ignore the / on <script/> and take the rest of the page
+            // for script, but the parser won't put anything inside a self-closed tag
+            self.state = match tag.name.as_str() {
+                _ if tag.self_closing => State::Data,
+                "textarea" | "title" => State::RcData,
+                "script" | "style" => State::RawText,
+                _ => State::Data,
+            };
+            self.last_start_tag = tag.name.clone();
+            Token::StartTag { name: tag.name, attributes: tag.attributes, self_closing: tag.self_closing }
+        };
+        self.tokens.push(token);
+    }
+
+    fn emit_comment(&mut self) {
+        self.flush_text();
+        let comment = std::mem::take(&mut self.comment);
+        self.tokens.push(Token::Comment(comment));
+        self.state = State::Data;
+    }
+
+    /// Called just after an &, and returns what it stands for. Anything that isn't a
+    /// character reference comes back as the & it started with, leaving the rest of it
+    /// to be read as ordinary text. The result is never looked at again, so &amp;lt;
+    /// stays as &lt;
+    fn consume_character_reference(&mut self, in_attribute: bool) -> String {
+        match self.input.get(self.pos) {
+            Some('#') => self.consume_numeric_reference(),
+            Some(c) if c.is_ascii_alphanumeric() => self.consume_named_reference(in_attribute),
+            _ => "&".to_string(),
+        }
+    }
+
+    /// &copy; and the like. The longest name that matches wins, so &notin; is ∉ rather
+    /// than ¬in, and the old names that can go without a ; can be run into what follows
+    fn consume_named_reference(&mut self, in_attribute: bool) -> String {
+        let mut candidate: String = self.input[self.pos..].iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .take(LONGEST_NAME)
+            .collect();
+        if candidate.len() < LONGEST_NAME && self.input.get(self.pos + candidate.len()) == Some(&';') {
+            candidate.push(';');
+        }
+
+        let found = (1..=candidate.len()).rev().find_map(|len| {
+            let name = &candidate[..len];
+            NAMED_REFERENCES.binary_search_by_key(&name, |&(name, _)| name).ok()
+                .map(|i| (name, NAMED_REFERENCES[i].1))
+        });
+        let (name, value) = match found {
+            Some(found) => found,
+            None => return "&".to_string(),
+        };
+
+        // in an attribute, ?a=1&copy=2 is a url rather than a ©, so a name with no ;
+        // only counts if it isn't followed by more of a name or an =
+        let next = self.input.get(self.pos + name.len()).copied();
+        if in_attribute && !name.ends_with(';') && next.is_some_and(|c| c == '=' || c.is_ascii_alphanumeric()) {
+            return "&".to_string();
+        }
+        self.pos += name.len();
+        value.to_string()
+    }
+
+    /// &#8217; and &#x2014;, with the ; optional
+    fn consume_numeric_reference(&mut self) -> String {
+        let hex = self.input.get(self.pos + 1).is_some_and(|&c| c == 'x' || c == 'X');
+        let radix = if hex { 16 } else { 10 };
+        let start = self.pos + if hex { 2 } else { 1 };
+
+        let mut end = start;
+        let mut code: u32 = 0;
+        while let Some(digit) = self.input.get(end).and_then(|c| c.to_digit(radix)) {
+            // anything too big is going to be replaced anyway, so stop it overflowing
+            code = code.saturating_mul(radix).saturating_add(digit).min(0x110000);
+            end += 1;
+        }
+        if end == start {
+            // &# with no number is just text
+            return "&".to_string();
+        }
+        self.pos = end;
+        if self.input.get(self.pos) == Some(&';') {
+            self.pos += 1;
+        }
+
+        let c = match code {
+            0 => '\u{fffd}',
+            // pages written for windows-1252 use its codes for curly quotes and the like
+            0x80..=0x9f => windows_1252(code).or_else(|| char::from_u32(code)).unwrap(),
+            // out of range, and surrogates, which from_u32 turns down
+            _ => char::from_u32(code).unwrap_or('\u{fffd}'),
+        };
+        c.to_string()
+    }
+}
+
+/// What the C1 control codes stand for in windows-1252, which the standard says a numeric
+/// reference to one of them means
+fn windows_1252(code: u32) -> Option<char> {
+    let c = match code {
+        0x80 => '\u{20ac}',
+        0x82 => '\u{201a}',
+        0x83 => '\u{192}',
+        0x84 => '\u{201e}',
+        0x85 => '\u{2026}',
+        0x86 => '\u{2020}',
+        0x87 => '\u{2021}',
+        0x88 => '\u{2c6}',
+        0x89 => '\u{2030}',
+        0x8a => '\u{160}',
+        0x8b => '\u{2039}',
+        0x8c => '\u{152}',
+        0x8e => '\u{17d}',
+        0x91 => '\u{2018}',
+        0x92 => '\u{2019}',
+        0x93 => '\u{201c}',
+        0x94 => '\u{201d}',
+        0x95 => '\u{2022}',
+        0x96 => '\u{2013}',
+        0x97 => '\u{2014}',
+        0x98 => '\u{2dc}',
+        0x99 => '\u{2122}',
+        0x9a => '\u{161}',
+        0x9b => '\u{203a}',
+        0x9c => '\u{153}',
+        0x9e => '\u{17e}',
+        0x9f => '\u{178}',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// The whitespace the tokenizer cares about: tab, line feed, form feed, carriage return and space
+fn is_whitespace(c: char) -> bool {
+    ['\t', '\n', '\u{c}', '\r', ' '].contains(&c)
+}
+
+fn lowercase(c: char) -> char {
+    c.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(name: &str, attributes: &[(&str, &str)]) -> Token {
+        Token::StartTag {
+            name: name.to_string(),
+            attributes: attributes.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            self_closing: false,
+        }
+    }
+
+    fn end(name: &str) -> Token {
+        Token::EndTag { name: name.to_string() }
+    }
+
+    fn text(text: &str) -> Token {
+        Token::Text(text.to_string())
+    }
+
+    #[test]
+    fn splits_tags_from_text() {
+        assert_eq!(tokenize("<!DOCTYPE html><P Class=x>Hi <b>there</B></p>"), vec![
+            Token::Doctype("html".to_string()),
+            start("p", &[("class", "x")]),
+            text("Hi "),
+            start("b", &[]),
+            text("there"),
+            end("b"),
+            end("p"),
+        ]);
+    }
+
+    #[test]
+    fn quoted_values_can_hold_anything_but_their_quote() {
+        assert_eq!(tokenize(r#"<a href="x>y" title='say "hi"' data-id=a/b>"#), vec![
+            start("a", &[("href", "x>y"), ("title", "say \"hi\""), ("data-id", "a/b")]),
+        ]);
+    }
+
+    #[test]
+    fn self_closing_tags_say_so() {
+        assert_eq!(tokenize("<br/>"), vec![Token::StartTag { name: "br".to_string(), attributes: Vec::new(), self_closing: true }]);
+    }
+
+    #[test]
+    fn stray_angle_brackets_are_text() {
+        assert_eq!(tokenize("a < b > c <"), vec![text("a < b > c <")]);
+        assert_eq!(tokenize("</>x"), vec![text("x")]);
+    }
+
+    #[test]
+    fn comments_run_to_the_first_close() {
+        assert_eq!(tokenize("<!-- <p>a > b</p> --x-->y"), vec![
+            Token::Comment(" <p>a > b</p> --x".to_string()),
+            text("y"),
+        ]);
+        assert_eq!(tokenize("<!-->x"), vec![Token::Comment(String::new()), text("x")]);
+        assert_eq!(tokenize("<?xml version=\"1.0\"?>"), vec![Token::Comment("?xml version=\"1.0\"?".to_string())]);
+    }
+
+    #[test]
+    fn unfinished_markup_at_the_end_is_dropped() {
+        assert_eq!(tokenize("text<p class"), vec![text("text")]);
+        assert_eq!(tokenize("<!-- never closed"), vec![Token::Comment(" never closed".to_string())]);
+    }
+
+    #[test]
+    fn script_and_style_are_raw_text() {
+        assert_eq!(tokenize("<script>if (a<b && c>d) x = '</p>'; </scripts></SCRIPT >y"), vec![
+            start("script", &[]),
+            text("if (a<b && c>d) x = '</p>'; </scripts>"),
+            end("script"),
+            text("y"),
+        ]);
+        assert_eq!(tokenize("<style>p > a { content: '&amp;' }</style>"), vec![
+            start("style", &[]),
+            text("p > a { content: '&amp;' }"),
+            end("style"),
+        ]);
+    }
+
+    #[test]
+    fn textarea_and_title_decode_references_but_not_tags() {
+        assert_eq!(tokenize("<title>A &amp; <b>B</title><textarea>1 &lt; 2</textarea>"), vec![
+            start("title", &[]),
+            text("A & <b>B"),
+            end("title"),
+            start("textarea", &[]),
+            text("1 < 2"),
+            end("textarea"),
+        ]);
+    }
+
+    #[test]
+    fn decodes_named_references_once() {
+        assert_eq!(tokenize("&amp;lt; &nbsp;&copy;&mdash;&NotEqualTilde;"), vec![text("&lt; \u{a0}©—≂̸")]);
+        assert_eq!(tokenize("&bogus; AT&T &"), vec![text("&bogus; AT&T &")]);
+    }
+
+    #[test]
+    fn the_longest_name_wins_and_old_names_can_skip_the_semicolon() {
+        assert_eq!(tokenize("&notin; &notit; &copy2020 &amp"), vec![text("∉ ¬it; ©2020 &")]);
+    }
+
+    #[test]
+    fn decodes_numeric_references() {
+        assert_eq!(tokenize("&#8217;&#x2014;&#X41&#65x"), vec![text("’—AAx")]);
+        assert_eq!(tokenize("&#; &#x; &#xZ"), vec![text("&#; &#x; &#xZ")]);
+    }
+
+    #[test]
+    fn replaces_numbers_that_arent_characters() {
+        // windows-1252 codes get the character they meant there
+        assert_eq!(tokenize("&#150;&#x80;&#x81;"), vec![text("–€\u{81}")]);
+        assert_eq!(tokenize("&#0;&#xD800;&#x110000;&#99999999999999999999;"), vec![text("\u{fffd}\u{fffd}\u{fffd}\u{fffd}")]);
+    }
+
+    #[test]
+    fn attributes_leave_url_like_references_alone() {
+        assert_eq!(tokenize(r#"<a href="?a=1&copy=2&copyx&amp=3&copy;" title=&lt&gt>"#), vec![
+            start("a", &[("href", "?a=1&copy=2&copyx&amp=3©"), ("title", "<>")]),
+        ]);
+    }
+
+    #[test]
+    fn boolean_attributes_are_empty_and_the_first_duplicate_wins() {
+        assert_eq!(tokenize("<input disabled aria-label=x ID=a id=b checked=''>"), vec![
+            start("input", &[("disabled", ""), ("aria-label", "x"), ("id", "a"), ("checked", "")]),
+        ]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::Response;
+
+/// A response we've kept, along with when it stops being fresh
+#[derive(Clone)]
+pub struct CacheEntry {
+    pub response: Response,
+    /// None means it has to be revalidated before every use
+    pub fresh_until: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        match self.fresh_until {
+            Some(fresh_until) => SystemTime::now() < fresh_until,
+            None => false,
+        }
+    }
+
+    /// The If-None-Match/If-Modified-Since headers that ask the server whether our copy
+    /// is still good
+    pub fn validators(&self) -> Vec<(&'static str, String)> {
+        let mut validators = Vec::new();
+        if let Some(etag) = self.response.headers.get("etag") {
+            validators.push(("If-None-Match", etag.clone()));
+        }
+        if let Some(last_modified) = self.response.headers.get("last-modified") {
+            validators.push(("If-Modified-Since", last_modified.clone()));
+        }
+        validators
+    }
+}
+
+/// An HTTP cache keyed by url. Entries live in memory, and also in `dir` if we were given
+/// one so they survive between runs
+pub struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(dir: Option<PathBuf>) -> Cache {
+        if let Some(ref dir) = dir {
+            // if this fails every write will too, and we just run without the disk cache
+            let _ = fs::create_dir_all(dir);
+        }
+        Cache { entries: HashMap::new(), dir }
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<&CacheEntry> {
+        if !self.entries.contains_key(url) {
+            let entry = self.read_from_disk(url)?;
+            self.entries.insert(url.to_string(), entry);
+        }
+        self.entries.get(url)
+    }
+
+    /// Keeps a copy of a response if its headers allow it, and forgets any old copy if not
+    pub fn store(&mut self, url: &str, response: &Response) {
+        let directives = cache_control(&response.headers);
+        let vary_all = response.headers.get("vary").map(|v| v.trim() == "*").unwrap_or(false);
+        if response.status != 200 || directives.contains_key("no-store") || vary_all {
+            self.remove(url);
+            return;
+        }
+
+        let mut entry = CacheEntry {
+            response: response.clone(),
+            fresh_until: fresh_until(&response.headers),
+        };
+        // cookies were handled when the response arrived, replaying them later would be wrong
+        entry.response.headers.remove("set-cookie");
+        // a response that can never be fresh is only worth keeping if we can revalidate it
+        if entry.fresh_until.is_none() && entry.validators().is_empty() {
+            self.remove(url);
+            return;
+        }
+        self.write_to_disk(url, &entry);
+        self.entries.insert(url.to_string(), entry);
+    }
+
+    /// Handles a 304 Not Modified: our copy is still good, so update it with the new
+    /// headers and hand it back
+    pub fn freshen(&mut self, url: &str, not_modified: &Response) -> Option<Response> {
+        let mut entry = self.get(url)?.clone();
+        for (name, value) in not_modified.headers.iter() {
+            // the framing headers describe the empty 304 body rather than the stored one,
+            // and cookies were already handled when it arrived
+            if name != "content-length" && name != "transfer-encoding" && name != "set-cookie" {
+                entry.response.headers.insert(name.clone(), value.clone());
+            }
+        }
+        entry.fresh_until = fresh_until(&entry.response.headers);
+        let response = entry.response.clone();
+        self.write_to_disk(url, &entry);
+        self.entries.insert(url.to_string(), entry);
+        Some(response)
+    }
+
+    fn remove(&mut self, url: &str) {
+        self.entries.remove(url);
+        if let Some(path) = self.path_for(url) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn path_for(&self, url: &str) -> Option<PathBuf> {
+        // FNV-1a, which unlike the std hasher is guaranteed to give the same name next run
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in url.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        self.dir.as_ref().map(|dir| dir.join(format!("{:016x}", hash)))
+    }
+
+    /// On disk an entry is a few lines of text followed by the raw body:
+    /// the url, the fresh-until time in unix seconds (or "-"), the status line, the
+    /// headers and a blank line
+    fn write_to_disk(&self, url: &str, entry: &CacheEntry) {
+        let path = match self.path_for(url) {
+            Some(path) => path,
+            None => return,
+        };
+        let fresh_until = match entry.fresh_until.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+            Some(t) => t.as_secs().to_string(),
+            None => "-".to_string(),
+        };
+        let response = &entry.response;
+        let mut data = format!("{}\n{}\n{} {}\n", url, fresh_until, response.status, response.reason);
+        for (name, value) in response.headers.iter() {
+            data.push_str(&format!("{}: {}\n", name, value));
+        }
+        data.push('\n');
+        let mut data = data.into_bytes();
+        data.extend_from_slice(&response.body);
+        // the cache is best effort, a failed write just means a refetch next time
+        let _ = fs::write(path, data);
+    }
+
+    fn read_from_disk(&self, url: &str) -> Option<CacheEntry> {
+        let data = fs::read(self.path_for(url)?).ok()?;
+        parse_entry(url, &data).ok()
+    }
+}
+
+fn parse_entry(url: &str, data: &[u8]) -> io::Result<CacheEntry> {
+    let bad = || io::Error::new(io::ErrorKind::InvalidData, "bad cache entry");
+    let split = data.windows(2).position(|w| w == b"\n\n").ok_or_else(bad)?;
+    let head = String::from_utf8_lossy(&data[..split]);
+    let body = data[split + 2..].to_vec();
+
+    let mut lines = head.lines();
+    // two urls can share a file name, so check it's really ours
+    if lines.next() != Some(url) {
+        return Err(bad());
+    }
+    let fresh_until = match lines.next().ok_or_else(bad)? {
+        "-" => None,
+        secs => Some(UNIX_EPOCH.checked_add(Duration::from_secs(secs.parse().map_err(|_| bad())?)).ok_or_else(bad)?),
+    };
+    let status_line: Vec<_> = lines.next().ok_or_else(bad)?.splitn(2, ' ').collect();
+    let status = status_line[0].parse().map_err(|_| bad())?;
+    let reason = status_line.get(1).unwrap_or(&"").to_string();
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        let header: Vec<_> = line.splitn(2, ": ").collect();
+        if header.len() == 2 {
+            headers.insert(header[0].to_string(), header[1].to_string());
+        }
+    }
+
+    let response = Response { status, reason, headers, body, persistent: false };
+    Ok(CacheEntry { response, fresh_until })
+}
+
+/// Splits Cache-Control into its directives, lowercased, with any value they have
+fn cache_control(headers: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut directives = HashMap::new();
+    if let Some(cache_control) = headers.get("cache-control") {
+        for directive in cache_control.split(',') {
+            let directive: Vec<_> = directive.splitn(2, '=').collect();
+            let name = directive[0].trim().to_lowercase();
+            let value = directive.get(1).map(|v| v.trim().trim_matches('"')).unwrap_or("");
+            if !name.is_empty() {
+                directives.insert(name, value.to_string());
+            }
+        }
+    }
+    directives
+}
+
+/// A number of seconds, as in max-age and Age. Anything over 2^31 is cut down to that,
+/// as RFC 9111 section 1.2.2 says, digits too many to fit included
+fn delta_seconds(value: &str) -> Option<u64> {
+    const MAX_DELTA_SECONDS: u64 = 2_147_483_648;
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse().unwrap_or(MAX_DELTA_SECONDS).min(MAX_DELTA_SECONDS))
+}
+
+/// When a response stops being fresh: max-age if there is one, then Expires. no-cache,
+/// or neither of them, means it has to be checked with the server every time
+fn fresh_until(headers: &HashMap<String, String>) -> Option<SystemTime> {
+    let directives = cache_control(headers);
+    if directives.contains_key("no-cache") {
+        return None;
+    }
+    if let Some(max_age) = directives.get("max-age") {
+        // a max-age we can't read is treated as already stale
+        let max_age = delta_seconds(max_age).unwrap_or(0);
+        let age = headers.get("age").and_then(|age| delta_seconds(age)).unwrap_or(0);
+        return Some(SystemTime::now() + Duration::from_secs(max_age.saturating_sub(age)));
+    }
+    if let Some(expires) = headers.get("expires") {
+        // an invalid date, like "0", means already expired
+        let expires = httpdate::parse_http_date(expires).unwrap_or(UNIX_EPOCH);
+        // measure against the server's clock if it told us the time
+        let date = headers.get("date").and_then(|date| httpdate::parse_http_date(date).ok());
+        return match date {
+            Some(date) => match expires.duration_since(date) {
+                Ok(lifetime) => Some(SystemTime::now() + lifetime),
+                Err(_) => Some(UNIX_EPOCH),
+            },
+            None => Some(expires),
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    /// How many seconds from now fresh_until puts it, give or take the test's own run time
+    fn lifetime(pairs: &[(&str, &str)]) -> Option<u64> {
+        let until = fresh_until(&headers(pairs))?;
+        Some(until.duration_since(SystemTime::now()).map_or(0, |lifetime| lifetime.as_secs() + 1))
+    }
+
+    #[test]
+    fn max_age_counts_from_now_less_the_age() {
+        assert_eq!(lifetime(&[("cache-control", "public, max-age=60")]), Some(60));
+        assert_eq!(lifetime(&[("cache-control", "max-age=60"), ("age", "50")]), Some(10));
+        assert_eq!(lifetime(&[("cache-control", "max-age=60"), ("age", "100")]), Some(0));
+        assert_eq!(lifetime(&[("cache-control", "max-age=soon")]), Some(0));
+    }
+
+    #[test]
+    fn huge_max_ages_are_cut_down() {
+        assert_eq!(lifetime(&[("cache-control", "max-age=18446744073709551615")]), Some(2_147_483_648));
+        assert_eq!(lifetime(&[("cache-control", "max-age=99999999999999999999999")]), Some(2_147_483_648));
+    }
+
+    #[test]
+    fn max_age_wins_over_expires() {
+        let pairs = [("cache-control", "max-age=60"), ("expires", "Thu, 01 Jan 1970 00:00:00 GMT")];
+        assert_eq!(lifetime(&pairs), Some(60));
+    }
+
+    #[test]
+    fn expires_is_measured_against_the_servers_date() {
+        let pairs = [("date", "Sun, 06 Nov 1994 08:49:37 GMT"), ("expires", "Sun, 06 Nov 1994 08:50:37 GMT")];
+        assert_eq!(lifetime(&pairs), Some(60));
+        assert_eq!(fresh_until(&headers(&[("expires", "0")])), Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn no_cache_or_nothing_means_always_check() {
+        assert_eq!(fresh_until(&headers(&[("cache-control", "no-cache, max-age=60")])), None);
+        assert_eq!(fresh_until(&headers(&[])), None);
+    }
+}
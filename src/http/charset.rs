@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// How far into the document we look for a <meta> charset, same as the HTML prescan
+const PRESCAN_BYTES: usize = 1024;
+
+/// Turns the raw body into text, using the first of these that names an encoding:
+/// a byte order mark, the charset in Content-Type, or a <meta> tag near the top.
+/// With none of those we go with UTF-8 if the bytes are valid UTF-8, and the usual
+/// windows-1252 fallback if not
+pub fn decode_text(headers: &HashMap<String, String>, body: &[u8]) -> String {
+    let encoding = headers.get("content-type")
+        .and_then(|content_type| charset_param(content_type))
+        .or_else(|| prescan_meta(body))
+        .unwrap_or_else(|| match std::str::from_utf8(body) {
+            Ok(_) => UTF_8,
+            Err(_) => WINDOWS_1252,
+        });
+    // decode() sniffs for a BOM first, which wins over everything else
+    let (text, _encoding, _had_errors) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// Pulls the encoding out of a "text/html; charset=..." style value
+fn charset_param(content_type: &str) -> Option<&'static Encoding> {
+    for param in content_type.split(';').skip(1) {
+        let param: Vec<_> = param.splitn(2, '=').collect();
+        if param.len() == 2 && param[0].trim().eq_ignore_ascii_case("charset") {
+            let label = param[1].trim().trim_matches(|c| c == '"' || c == '\'');
+            return Encoding::for_label(label.as_bytes());
+        }
+    }
+    None
+}
+
+/// A cut-down version of the HTML encoding prescan. Looks through the start of the
+/// document for <meta charset=...> or <meta http-equiv content="...; charset=...">
+fn prescan_meta(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(PRESCAN_BYTES)];
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            // a commented out meta tag doesn't count
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => return None,
+            };
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if !(tag.starts_with("<meta ") || tag.starts_with("<meta\t") || tag.starts_with("<meta\n")) {
+            rest = &rest[rest.len().min(1)..];
+            continue;
+        }
+        if let Some(encoding) = meta_charset(tag) {
+            // A page can't really be UTF-16 if we could read its meta tag as ASCII
+            if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+                return Some(UTF_8);
+            }
+            if encoding == encoding_rs::X_USER_DEFINED {
+                return Some(WINDOWS_1252);
+            }
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+/// Finds "charset" in a meta tag and reads the label after it. That covers both the
+/// charset attribute and a charset inside a content attribute
+fn meta_charset(tag: &str) -> Option<&'static Encoding> {
+    let mut rest = tag;
+    while let Some(i) = rest.find("charset") {
+        rest = rest[i + "charset".len()..].trim_start();
+        if !rest.starts_with('=') {
+            continue;
+        }
+        let value = rest[1..].trim_start().trim_start_matches(['"', '\'']);
+        let end = value.find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+            .unwrap_or(value.len());
+        if let Some(encoding) = Encoding::for_label(&value.as_bytes()[..end]) {
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_type(value: &str) -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), value.to_string());
+        headers
+    }
+
+    #[test]
+    fn reads_the_content_type_charset() {
+        assert_eq!(charset_param("text/html; charset=ISO-8859-2"), Some(encoding_rs::ISO_8859_2));
+        assert_eq!(charset_param("text/html;charset=\"utf-8\""), Some(UTF_8));
+        assert_eq!(charset_param("text/html; charset=nonsense"), None);
+        assert_eq!(charset_param("text/html"), None);
+    }
+
+    #[test]
+    fn finds_a_meta_charset() {
+        assert_eq!(prescan_meta(b"<html><head><meta charset=shift_jis>"), Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(prescan_meta(b"<META CHARSET=\"KOI8-R\"/>"), Some(encoding_rs::KOI8_R));
+        let http_equiv = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\">";
+        assert_eq!(prescan_meta(http_equiv), Some(encoding_rs::WINDOWS_1251));
+        assert_eq!(prescan_meta(b"<metadata charset=shift_jis><p>none here</p>"), None);
+    }
+
+    #[test]
+    fn ignores_a_commented_out_meta() {
+        assert_eq!(prescan_meta(b"<!-- <meta charset=shift_jis> --><meta charset=euc-kr>"), Some(encoding_rs::EUC_KR));
+        assert_eq!(prescan_meta(b"<!-- <meta charset=shift_jis>"), None);
+    }
+
+    #[test]
+    fn a_utf16_meta_means_utf8() {
+        assert_eq!(prescan_meta(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(prescan_meta(b"<meta charset=x-user-defined>"), Some(WINDOWS_1252));
+    }
+
+    #[test]
+    fn decodes_with_the_meta_charset() {
+        // 日本 in Shift_JIS
+        let body = b"<meta charset=shift_jis><p>\x93\xfa\x96\x7b</p>";
+        assert_eq!(decode_text(&HashMap::new(), body), "<meta charset=shift_jis><p>日本</p>");
+    }
+
+    #[test]
+    fn a_byte_order_mark_wins() {
+        let body = b"\xef\xbb\xbfcaf\xc3\xa9";
+        assert_eq!(decode_text(&content_type("text/html; charset=windows-1252"), body), "café");
+        let body = b"\xff\xfeh\x00i\x00";
+        assert_eq!(decode_text(&content_type("text/plain; charset=utf-8"), body), "hi");
+    }
+
+    #[test]
+    fn falls_back_to_windows_1252_for_bytes_that_arent_utf8() {
+        assert_eq!(decode_text(&HashMap::new(), b"caf\xe9 \x93quoted\x94"), "café “quoted”");
+        assert_eq!(decode_text(&HashMap::new(), "café".as_bytes()), "café");
+    }
+}
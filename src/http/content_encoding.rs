@@ -0,0 +1,91 @@
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+use super::{FetchError, Response};
+
+/// What we send as Accept-Encoding, in order of preference
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// Undoes any Content-Encoding the server applied, so the body is the plain document.
+/// The header is dropped afterwards so nothing downstream tries to decode it twice
+pub fn decode_body(response: &mut Response) -> Result<(), FetchError> {
+    let encodings = match response.headers.remove("content-encoding") {
+        Some(encodings) => encodings,
+        None => return Ok(()),
+    };
+    // A 304, a redirect or any other empty body has nothing to decode, and the decoders
+    // all count empty input as a truncated stream
+    if response.body.is_empty() {
+        return Ok(());
+    }
+
+    // Codings are listed in the order they were applied, so undo them back to front
+    for encoding in encodings.rsplit(',') {
+        let encoding = encoding.trim().to_lowercase();
+        let body = &response.body[..];
+        let mut decoded = Vec::new();
+        let result = match encoding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => MultiGzDecoder::new(body).read_to_end(&mut decoded),
+            // deflate is meant to be zlib wrapped, but some servers send the raw stream
+            "deflate" => match ZlibDecoder::new(body).read_to_end(&mut decoded) {
+                Ok(n) => Ok(n),
+                Err(_) => {
+                    decoded.clear();
+                    DeflateDecoder::new(body).read_to_end(&mut decoded)
+                }
+            },
+            "br" => brotli_decompressor::Decompressor::new(body, 4096).read_to_end(&mut decoded),
+            _ => return Err(FetchError::MalformedResponse(format!("unsupported content encoding {:?}", encoding))),
+        };
+        if let Err(e) = result {
+            return Err(FetchError::MalformedResponse(format!("couldn't decode {} body: {}", encoding, e)));
+        }
+        response.body = decoded;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn response(status: u16, encoding: &str, body: Vec<u8>) -> Response {
+        let mut headers = HashMap::new();
+        headers.insert("content-encoding".to_string(), encoding.to_string());
+        Response { status, reason: String::new(), headers, body, persistent: true }
+    }
+
+    #[test]
+    fn decodes_gzip_and_drops_the_header() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let mut response = response(200, "gzip", encoder.finish().unwrap());
+        decode_body(&mut response).unwrap();
+        assert_eq!(response.body, b"hello");
+        assert!(!response.headers.contains_key("content-encoding"));
+    }
+
+    #[test]
+    fn leaves_an_empty_body_alone() {
+        for encoding in ["gzip", "deflate", "br"] {
+            let mut not_modified = response(304, encoding, Vec::new());
+            decode_body(&mut not_modified).unwrap();
+            assert!(not_modified.body.is_empty());
+            assert!(!not_modified.headers.contains_key("content-encoding"));
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cant_decode() {
+        assert!(decode_body(&mut response(200, "gzip", b"not gzip".to_vec())).is_err());
+        assert!(decode_body(&mut response(200, "compress", b"x".to_vec())).is_err());
+    }
+}
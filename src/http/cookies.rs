@@ -0,0 +1,441 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use url::Url;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// One cookie, as RFC 6265 section 5.3 stores them
+#[derive(Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lowercased, without a leading dot
+    pub domain: String,
+    /// Set when there was no Domain attribute, so only the exact host gets it back
+    pub host_only: bool,
+    pub path: String,
+    /// None for a session cookie, which lasts until Tundra quits
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+}
+
+impl Cookie {
+    fn is_expired(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires <= SystemTime::now(),
+            None => false,
+        }
+    }
+
+    fn matches(&self, scheme: &str, host: &str, path: &str) -> bool {
+        let domain_ok = if self.host_only { host == self.domain } else { domain_match(host, &self.domain) };
+        domain_ok && path_match(path, &self.path) && (!self.secure || scheme == "https") && !self.is_expired()
+    }
+
+    /// Whether the cookie's SameSite setting lets it go with a method request to a scheme
+    /// url, made from the initiator page. With no initiator the user asked for the page
+    /// themselves, which is never cross-site
+    ///
+    /// Without a public suffix list to find sites by, the initiator counts as the same
+    /// site when it's within the cookie's own domain. That can mistake a sibling
+    /// subdomain for another site, which only ever holds a cookie back
+    fn same_site_allows(&self, scheme: &str, method: &str, initiator: Option<&Url>) -> bool {
+        let same_site = match initiator {
+            None => true,
+            Some(page) => {
+                let host = match page.host() {
+                    Some(url::Host::Ipv6(address)) => address.to_string(),
+                    Some(host) => host.to_string(),
+                    None => String::new(),
+                };
+                page.scheme() == scheme && !host.is_empty() && domain_match(&host, &self.domain)
+            },
+        };
+        match self.same_site {
+            SameSite::Strict => same_site,
+            // every request Tundra makes is a top-level navigation, which Lax cookies go on
+            // unless it could change something, like a form POST
+            SameSite::Lax => same_site || method == "GET" || method == "HEAD",
+            SameSite::None => true,
+        }
+    }
+}
+
+/// Keeps the cookies servers hand out and gives them back on later requests.
+/// With a file, the persistent (non-session) cookies are saved there between runs
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    file: Option<PathBuf>,
+}
+
+impl CookieJar {
+    pub fn new(file: Option<PathBuf>) -> CookieJar {
+        let mut jar = CookieJar { cookies: Vec::new(), file };
+        if let Some(ref file) = jar.file {
+            // no file yet just means no cookies yet
+            if let Ok(data) = fs::read_to_string(file) {
+                jar.cookies = data.lines().filter_map(parse_saved_cookie).collect();
+            }
+        }
+        jar
+    }
+
+    /// Handles one Set-Cookie header received from scheme://host/path
+    pub fn set_cookie(&mut self, scheme: &str, host: &str, path: &str, header: &str) {
+        let host = host.to_lowercase();
+        let mut parts = header.split(';');
+        let name_value: Vec<_> = parts.next().unwrap().splitn(2, '=').collect();
+        if name_value.len() < 2 || name_value[0].trim().is_empty() {
+            return;
+        }
+
+        let mut cookie = Cookie {
+            name: name_value[0].trim().to_string(),
+            value: name_value[1].trim().to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(path),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: SameSite::Lax,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+
+        for attribute in parts {
+            let attribute: Vec<_> = attribute.splitn(2, '=').collect();
+            let value = attribute.get(1).map(|v| v.trim()).unwrap_or("");
+            match attribute[0].trim().to_lowercase().as_str() {
+                "expires" => expires = parse_cookie_date(value),
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                },
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+                    if !domain.is_empty() {
+                        // a server can only set cookies for itself or a parent domain
+                        if !domain_match(&host, &domain) {
+                            return;
+                        }
+                        // Without a public suffix list, a domain with no dot in it stands in for
+                        // one, so Domain=com can't hand a cookie to every .com site. As RFC 6265
+                        // section 5.3 says, it's only allowed as the host itself, host-only
+                        if !domain.contains('.') {
+                            if domain != host {
+                                return;
+                            }
+                            continue;
+                        }
+                        cookie.domain = domain;
+                        cookie.host_only = false;
+                    }
+                },
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_lowercase().as_str() {
+                        "strict" => SameSite::Strict,
+                        "none" => SameSite::None,
+                        _ => SameSite::Lax,
+                    };
+                },
+                _ => (),
+            }
+        }
+
+        // Max-Age wins over Expires, and anything at or below zero means delete it now
+        cookie.expires = match max_age {
+            Some(seconds) if seconds <= 0 => Some(UNIX_EPOCH),
+            Some(seconds) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+                Some(expiry_at(now.saturating_add(seconds as u64)))
+            },
+            None => expires,
+        };
+        // only a secure page gets to set a secure cookie
+        if cookie.secure && scheme != "https" {
+            return;
+        }
+
+        self.cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        if !cookie.is_expired() {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// The Cookie header to send with a method request to scheme://host/path, if any
+    /// cookies apply. initiator is the page the request came from, for SameSite to go by
+    pub fn cookie_header(&mut self, scheme: &str, host: &str, path: &str, method: &str, initiator: Option<&Url>)
+                         -> Option<String> {
+        self.cookies.retain(|cookie| !cookie.is_expired());
+        let host = host.to_lowercase();
+        let path = path.split(['?', '#']).next().unwrap();
+
+        let mut matching: Vec<&Cookie> = self.cookies.iter()
+            .filter(|cookie| cookie.matches(scheme, &host, path) && cookie.same_site_allows(scheme, method, initiator))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // longer paths first, as RFC 6265 section 5.4 asks
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching.iter().map(|c| format!("{}={}", c.name, c.value)).collect();
+        Some(pairs.join("; "))
+    }
+
+    /// Writes the persistent cookies out to the jar's file, one tab separated line each:
+    /// domain, host-only, path, secure, http-only, samesite, expiry (unix seconds), name, value
+    pub fn save(&self) -> io::Result<()> {
+        let file = match self.file {
+            Some(ref file) => file,
+            None => return Ok(()),
+        };
+        let mut data = String::new();
+        for cookie in self.cookies.iter() {
+            let expires = match cookie.expires.and_then(|e| e.duration_since(UNIX_EPOCH).ok()) {
+                Some(expires) => expires.as_secs(),
+                // session cookies die with the session
+                None => continue,
+            };
+            let same_site = match cookie.same_site {
+                SameSite::Strict => "strict",
+                SameSite::Lax => "lax",
+                SameSite::None => "none",
+            };
+            data.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                                   cookie.domain, cookie.host_only, cookie.path, cookie.secure,
+                                   cookie.http_only, same_site, expires, cookie.name, cookie.value));
+        }
+        fs::write(file, data)
+    }
+}
+
+fn parse_saved_cookie(line: &str) -> Option<Cookie> {
+    let fields: Vec<_> = line.splitn(9, '\t').collect();
+    if fields.len() != 9 {
+        return None;
+    }
+    let cookie = Cookie {
+        domain: fields[0].to_string(),
+        host_only: fields[1] == "true",
+        path: fields[2].to_string(),
+        secure: fields[3] == "true",
+        http_only: fields[4] == "true",
+        same_site: match fields[5] {
+            "strict" => SameSite::Strict,
+            "none" => SameSite::None,
+            _ => SameSite::Lax,
+        },
+        expires: Some(expiry_at(fields[6].parse().ok()?)),
+        name: fields[7].to_string(),
+        value: fields[8].to_string(),
+    };
+    if cookie.is_expired() {
+        return None;
+    }
+    Some(cookie)
+}
+
+/// The latest a cookie can last until, the end of the year 9999. A Max-Age can ask for
+/// far more than SystemTime holds, and RFC 6265 section 5.2.2 lets us cut it down
+const LATEST_EXPIRY: u64 = 253_402_300_799;
+
+/// The time that many seconds after the epoch, or LATEST_EXPIRY if that's sooner
+fn expiry_at(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.min(LATEST_EXPIRY))
+}
+
+/// host is domain, or a subdomain of it. An IP address only matches itself
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[');
+    !is_ip && host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+}
+
+/// The request path is the cookie's path or somewhere underneath it
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The "directory" of the request path, used when a cookie doesn't give its own path
+fn default_path(path: &str) -> String {
+    let path = path.split(['?', '#']).next().unwrap();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
+
+/// Cookie dates come in every format under the sun, so this follows the forgiving
+/// algorithm in RFC 6265 section 5.1.1 rather than expecting a proper HTTP date
+fn parse_cookie_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun",
+                                "jul", "aug", "sep", "oct", "nov", "dec"];
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    let is_delimiter = |c: char| !(c.is_ascii_alphanumeric() || c == ':');
+    for token in date.split(is_delimiter).filter(|t| !t.is_empty()) {
+        let digits: String = token.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if time.is_none() {
+            let parts: Vec<u64> = token.split(':').filter_map(|p| {
+                let p: String = p.chars().take_while(|c| c.is_ascii_digit()).collect();
+                p.parse().ok()
+            }).collect();
+            if parts.len() == 3 && token.matches(':').count() == 2 {
+                time = Some((parts[0], parts[1], parts[2]));
+                continue;
+            }
+        }
+        if day.is_none() && !digits.is_empty() && digits.len() <= 2 {
+            day = digits.parse::<u64>().ok();
+            continue;
+        }
+        if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_lowercase();
+            if let Some(i) = MONTHS.iter().position(|m| *m == prefix) {
+                month = Some(i as u64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() && (2..=4).contains(&digits.len()) {
+            year = digits.parse::<u64>().ok();
+            continue;
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if year < 70 {
+        year += 2000;
+    } else if year < 100 {
+        year += 1900;
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    if year < 1970 {
+        return Some(UNIX_EPOCH);
+    }
+
+    // days since the epoch, from Howard Hinnant's days_from_civil
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(expiry_at(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(seconds: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn parses_the_usual_date_formats() {
+        // 1994-11-06 08:49:37 UTC, in the three formats RFC 7231 knows about
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1994 08:49:37 GMT"), date(784_111_777));
+        assert_eq!(parse_cookie_date("Sunday, 06-Nov-94 08:49:37 GMT"), date(784_111_777));
+        assert_eq!(parse_cookie_date("Sun Nov  6 08:49:37 1994"), date(784_111_777));
+        assert_eq!(parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"), date(0));
+    }
+
+    #[test]
+    fn turns_down_dates_that_dont_add_up() {
+        assert_eq!(parse_cookie_date("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_cookie_date("06 Nov 1994"), None);
+        assert_eq!(parse_cookie_date("0"), None);
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1600 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn sends_cookies_back_where_they_belong() {
+        let mut jar = CookieJar::new(None);
+        jar.set_cookie("https", "www.example.com", "/shop/cart", "id=1");
+        jar.set_cookie("https", "www.example.com", "/", "wide=2; Domain=.example.com; Path=/");
+        jar.set_cookie("https", "www.example.com", "/", "safe=3; Secure");
+        // not a domain this host can set cookies for
+        jar.set_cookie("https", "www.example.com", "/", "evil=4; Domain=other.com");
+        // nor one that's a whole top-level domain
+        jar.set_cookie("https", "www.example.com", "/", "tld=5; Domain=com");
+
+        assert_eq!(jar.cookie_header("https", "www.example.com", "/shop/cart/items", "GET", None).as_deref(),
+                   Some("id=1; wide=2; safe=3"));
+        assert_eq!(jar.cookie_header("http", "example.com", "/", "GET", None).as_deref(), Some("wide=2"));
+        assert_eq!(jar.cookie_header("https", "other.com", "/", "GET", None), None);
+        assert_eq!(jar.cookie_header("https", "shop.com", "/", "GET", None), None);
+    }
+
+    #[test]
+    fn a_single_label_domain_only_goes_back_to_that_host() {
+        let mut jar = CookieJar::new(None);
+        jar.set_cookie("http", "localhost", "/", "a=1; Domain=localhost");
+        assert_eq!(jar.cookie_header("http", "localhost", "/", "GET", None).as_deref(), Some("a=1"));
+        assert_eq!(jar.cookie_header("http", "app.localhost", "/", "GET", None), None);
+    }
+
+    #[test]
+    fn expires_and_replaces_cookies() {
+        let mut jar = CookieJar::new(None);
+        jar.set_cookie("http", "example.com", "/", "a=1");
+        jar.set_cookie("http", "example.com", "/", "a=2");
+        jar.set_cookie("http", "example.com", "/", "b=1; Max-Age=60");
+        jar.set_cookie("http", "example.com", "/", "c=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(jar.cookie_header("http", "example.com", "/", "GET", None).as_deref(), Some("a=2; b=1"));
+
+        // Max-Age wins over Expires, and zero deletes
+        jar.set_cookie("http", "example.com", "/", "b=1; Max-Age=0; Expires=Fri, 01 Jan 9999 00:00:00 GMT");
+        assert_eq!(jar.cookie_header("http", "example.com", "/", "GET", None).as_deref(), Some("a=2"));
+    }
+
+    #[test]
+    fn clamps_a_huge_max_age() {
+        let mut jar = CookieJar::new(None);
+        jar.set_cookie("http", "example.com", "/", "a=1; Max-Age=9223372036854775807");
+        assert_eq!(jar.cookies[0].expires, Some(expiry_at(LATEST_EXPIRY)));
+    }
+
+    #[test]
+    fn holds_same_site_cookies_back_from_other_sites() {
+        let mut jar = CookieJar::new(None);
+        jar.set_cookie("https", "bank.test", "/", "strict=1; SameSite=Strict");
+        jar.set_cookie("https", "bank.test", "/", "lax=2");
+        jar.set_cookie("https", "bank.test", "/", "none=3; SameSite=None; Secure");
+        let other_site = Url::parse("https://evil.test/").unwrap();
+        let same_site = Url::parse("https://www.bank.test/").unwrap();
+        let mut header = |method, initiator| jar.cookie_header("https", "bank.test", "/", method, initiator);
+
+        assert_eq!(header("POST", None).as_deref(), Some("strict=1; lax=2; none=3"));
+        assert_eq!(header("POST", Some(&same_site)).as_deref(), Some("strict=1; lax=2; none=3"));
+        assert_eq!(header("GET", Some(&other_site)).as_deref(), Some("lax=2; none=3"));
+        assert_eq!(header("POST", Some(&other_site)).as_deref(), Some("none=3"));
+    }
+}
@@ -0,0 +1,379 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::pki_types::pem::PemObject;
+
+mod cache;
+mod charset;
+mod content_encoding;
+mod cookies;
+mod pool;
+mod proxy;
+mod response;
+
+pub use self::cache::Cache;
+pub use self::charset::decode_text;
+pub use self::content_encoding::{decode_body, ACCEPT_ENCODING};
+pub use self::cookies::CookieJar;
+pub use self::pool::ConnectionPool;
+pub use self::proxy::{Proxies, Proxy};
+pub use self::response::{read_response, Response};
+
+/// Everything that can go wrong between asking for a page and getting its body
+#[derive(Debug)]
+pub enum FetchError {
+    /// The url itself didn't make sense
+    BadUrl(String),
+    UnsupportedScheme(String),
+    /// A local file couldn't be read
+    File(String, io::Error),
+    /// The host name didn't resolve to any address
+    Dns(String),
+    ConnectionRefused(String),
+    /// The proxy couldn't get us through to the server
+    Proxy(String),
+    Timeout(TimeoutKind),
+    /// The certificate or handshake was rejected
+    Tls(String),
+    /// Any other socket failure
+    Io(io::Error),
+    /// The server sent something that isn't HTTP
+    MalformedResponse(String),
+    /// The server answered with a 4xx or 5xx status
+    Http(u16, String),
+    RedirectLoop(String),
+    TooManyRedirects(usize),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::BadUrl(url) => write!(f, "{} is not a valid address", url),
+            FetchError::UnsupportedScheme(scheme) => write!(f, "Tundra doesn't know how to open {} addresses", scheme),
+            FetchError::File(path, e) => write!(f, "Couldn't read {}: {}", path, e),
+            FetchError::Dns(host) => write!(f, "Couldn't find the server at {}", host),
+            FetchError::ConnectionRefused(address) => write!(f, "{} refused the connection", address),
+            FetchError::Proxy(reason) => write!(f, "Couldn't connect through the proxy: {}", reason),
+            FetchError::Timeout(TimeoutKind::Connect(address)) => write!(f, "{} took too long to accept the connection", address),
+            FetchError::Timeout(TimeoutKind::Read) => write!(f, "The server took too long to respond"),
+            FetchError::Timeout(TimeoutKind::Total(limit)) => {
+                write!(f, "The page took longer than {} seconds to load", limit.as_secs())
+            },
+            FetchError::Tls(reason) => write!(f, "Couldn't make a secure connection: {}", reason),
+            FetchError::Io(e) => write!(f, "The connection failed: {}", e),
+            FetchError::MalformedResponse(reason) => write!(f, "The server sent a malformed response: {}", reason),
+            FetchError::Http(status, reason) => write!(f, "The server returned {} {}", status, reason),
+            FetchError::RedirectLoop(url) => write!(f, "The page keeps redirecting back to {}", url),
+            FetchError::TooManyRedirects(n) => write!(f, "Gave up after {} redirects", n),
+        }
+    }
+}
+
+/// Which limit a request ran into
+#[derive(Debug)]
+pub enum TimeoutKind {
+    /// The server at this address never finished the TCP handshake
+    Connect(String),
+    /// The server went quiet for longer than the read timeout
+    Read,
+    /// The whole fetch, redirects included, went past its deadline
+    Total(Duration),
+}
+
+/// How long to wait on the network. None means wait as long as it takes
+#[derive(Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    /// The longest gap allowed between bytes from the server
+    pub read: Option<Duration>,
+    /// The most a whole fetch can take, however busy the server keeps us
+    pub total: Option<Duration>,
+}
+
+impl From<io::Error> for FetchError {
+    fn from(e: io::Error) -> FetchError {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => FetchError::Timeout(TimeoutKind::Read),
+            io::ErrorKind::ConnectionRefused => FetchError::ConnectionRefused(e.to_string()),
+            _ => match e.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+                Some(tls_error) => FetchError::Tls(tls_error.to_string()),
+                None => FetchError::Io(e),
+            },
+        }
+    }
+}
+
+/// Our end of a connection to a server. Plain http talks straight over the socket,
+/// https wraps the socket in a TLS session first
+pub struct Stream {
+    transport: Transport,
+    read_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+enum Transport {
+    Plain(TcpStream),
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    /// Makes reads fail once `deadline` passes, even if the server keeps sending.
+    /// Connections outlive requests in the pool, so each request sets its own
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    fn apply_timeouts(&self) -> io::Result<()> {
+        // without a deadline the timeouts set in connect() never change
+        if self.deadline.is_none() {
+            return Ok(());
+        }
+        let socket = match self.transport {
+            Transport::Plain(ref socket) => socket,
+            Transport::Tls(ref tls) => &tls.sock,
+        };
+        set_socket_timeouts(socket, self.read_timeout, self.deadline)
+    }
+}
+
+/// Sets the socket's timeouts to whichever comes first, the read timeout or the deadline
+fn set_socket_timeouts(socket: &TcpStream, read_timeout: Option<Duration>, deadline: Option<Instant>) -> io::Result<()> {
+    let timeout = match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // a zero timeout means none at all to the socket
+            if remaining == Duration::from_secs(0) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "deadline passed"));
+            }
+            Some(read_timeout.map_or(remaining, |read_timeout| read_timeout.min(remaining)))
+        },
+        None => read_timeout,
+    };
+    socket.set_read_timeout(timeout)?;
+    socket.set_write_timeout(timeout)
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.apply_timeouts()?;
+        match self.transport {
+            Transport::Plain(ref mut socket) => socket.read(buf),
+            // A server hanging up without sending close_notify comes out as UnexpectedEof.
+            // That's left for read_response to judge, since it's only harmless when the
+            // body's length was given some other way
+            Transport::Tls(ref mut tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.apply_timeouts()?;
+        match self.transport {
+            Transport::Plain(ref mut socket) => socket.write(buf),
+            Transport::Tls(ref mut tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.transport {
+            Transport::Plain(ref mut socket) => socket.flush(),
+            Transport::Tls(ref mut tls) => tls.flush(),
+        }
+    }
+}
+
+/// Builds the TLS client configuration used for every https connection.
+///
+/// Certificates are checked against the Mozilla root store. `ca_file` adds the
+/// certificates from a PEM file on top of those, which is how you point Tundra at a
+/// local server with a self-signed certificate
+pub fn tls_config(ca_file: Option<&str>) -> io::Result<Arc<rustls::ClientConfig>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    if let Some(ca_file) = ca_file {
+        let certs = CertificateDer::pem_file_iter(ca_file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for cert in certs {
+            let cert = cert.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            roots.add(cert).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+    }
+
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Opens a connection to host:port, doing the TLS handshake for https.
+/// The host doubles as the SNI name and the name the certificate is checked against.
+///
+/// With a proxy, the connection is made to the proxy instead. Unless it's an HTTP proxy
+/// we'll be forwarding plain http requests to, it's then asked to tunnel through to
+/// host:port, and everything after that (TLS included) runs through the tunnel.
+///
+/// The connect timeout applies to each address the host resolves to. Name lookup itself
+/// can't be interrupted, so it isn't covered by any of the timeouts
+pub fn connect(scheme: &str, host: &str, port: u16, tls: &Arc<rustls::ClientConfig>,
+               timeouts: &Timeouts, deadline: Option<Instant>, proxy: Option<&Proxy>) -> Result<Stream, FetchError> {
+    let (connect_host, connect_port) = match proxy {
+        Some(proxy) => (proxy.host.as_str(), proxy.port),
+        None => (host, port),
+    };
+    let address = format!("{}:{}", connect_host, connect_port);
+    // Resolve separately from connecting so a bad host name can be told apart from a dead server
+    let addresses: Vec<_> = match (connect_host, connect_port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(_) => return Err(FetchError::Dns(connect_host.to_string())),
+    };
+    if addresses.is_empty() {
+        return Err(FetchError::Dns(connect_host.to_string()));
+    }
+    let mut socket = connect_socket(&addresses, &address, timeouts.connect, deadline)?;
+    set_socket_timeouts(&socket, timeouts.read, deadline)?;
+    if let Some(proxy) = proxy {
+        if !proxy.forwards(scheme) {
+            proxy.tunnel(&mut socket, host, port)?;
+        }
+    }
+    let stream = |transport| Stream { transport, read_timeout: timeouts.read, deadline };
+    if scheme != "https" {
+        return Ok(stream(Transport::Plain(socket)));
+    }
+
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|_| FetchError::BadUrl(host.to_string()))?;
+    let connection = rustls::ClientConnection::new(tls.clone(), server_name)
+        .map_err(|e| FetchError::Tls(e.to_string()))?;
+    let mut tls_stream = rustls::StreamOwned::new(connection, socket);
+    // Drive the handshake now so certificate problems show up as connection errors
+    // rather than on the first read
+    while tls_stream.conn.is_handshaking() {
+        tls_stream.conn.complete_io(&mut tls_stream.sock)?;
+    }
+    Ok(stream(Transport::Tls(Box::new(tls_stream))))
+}
+
+/// Tries each address in turn until one accepts, like TcpStream::connect does, but
+/// giving up on each after the connect timeout or once the deadline has passed
+fn connect_socket(addresses: &[SocketAddr], address: &str, connect_timeout: Option<Duration>,
+                  deadline: Option<Instant>) -> Result<TcpStream, FetchError> {
+    let mut last_error = FetchError::ConnectionRefused(address.to_string());
+    for socket_address in addresses {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let timeout = match (connect_timeout, remaining) {
+            (Some(connect_timeout), Some(remaining)) => Some(connect_timeout.min(remaining)),
+            (connect_timeout, remaining) => connect_timeout.or(remaining),
+        };
+        let result = match timeout {
+            // connect_timeout() rejects a zero timeout, and there's no time left anyway
+            Some(timeout) if timeout == Duration::from_secs(0) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "deadline passed"))
+            },
+            Some(timeout) => TcpStream::connect_timeout(socket_address, timeout),
+            None => TcpStream::connect(socket_address),
+        };
+        last_error = match result {
+            Ok(socket) => return Ok(socket),
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                FetchError::ConnectionRefused(address.to_string())
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                FetchError::Timeout(TimeoutKind::Connect(address.to_string()))
+            },
+            Err(e) => e.into(),
+        };
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use rustls::pki_types::PrivateKeyDer;
+
+    use super::*;
+
+    // A test CA, and a certificate for localhost and 127.0.0.1 that it signed. Both were
+    // made with openssl to last a hundred years, and nothing in the Mozilla roots trusts them
+    const TEST_CA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem");
+    const TEST_CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/localhost.pem");
+    const TEST_KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/localhost.key");
+
+    const TIMEOUTS: Timeouts = Timeouts {
+        connect: Some(Duration::from_secs(5)),
+        read: Some(Duration::from_secs(5)),
+        total: None,
+    };
+
+    /// Starts an https server on 127.0.0.1 that answers one request with body, and returns
+    /// its port. A client that won't finish the handshake just ends the thread early
+    fn serve_once(body: &'static str) -> u16 {
+        let certs = CertificateDer::pem_file_iter(TEST_CERT).unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let key = PrivateKeyDer::from_pem_file(TEST_KEY).unwrap();
+        let config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let connection = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            let mut tls = BufReader::new(rustls::StreamOwned::new(connection, socket));
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                if io::BufRead::read_line(&mut tls, &mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+            }
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            let tls = tls.get_mut();
+            let _ = tls.write_all(response.as_bytes());
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        });
+        port
+    }
+
+    fn fetch(host: &str, port: u16, tls: &Arc<rustls::ClientConfig>) -> Result<Response, FetchError> {
+        let mut stream = connect("https", host, port, tls, &TIMEOUTS, None, None)?;
+        stream.write_all(format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host).as_bytes())?;
+        read_response(&mut BufReader::new(stream), false)
+    }
+
+    #[test]
+    fn fetches_from_a_server_signed_by_the_ca_file() {
+        let tls = tls_config(Some(TEST_CA)).unwrap();
+        for host in ["localhost", "127.0.0.1"] {
+            let port = serve_once("hello");
+            let response = fetch(host, port, &tls).unwrap();
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"hello");
+        }
+    }
+
+    #[test]
+    fn rejects_the_server_without_the_ca_file() {
+        let port = serve_once("hello");
+        match fetch("localhost", port, &tls_config(None).unwrap()) {
+            Err(FetchError::Tls(_)) => (),
+            Err(e) => panic!("expected a certificate error, got {:?}", e),
+            Ok(_) => panic!("a certificate no root vouches for was accepted"),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+
+use super::Stream;
+
+/// A connection is pooled per (scheme, host, port)
+type PoolKey = (String, String, u16);
+
+struct IdleConnection {
+    reader: BufReader<Stream>,
+    since: Instant,
+}
+
+/// Holds on to persistent connections between requests, so fetching from the same
+/// server again can skip the TCP (and TLS) handshake
+pub struct ConnectionPool {
+    idle: HashMap<PoolKey, Vec<IdleConnection>>,
+    max_per_host: usize,
+    idle_timeout: Duration,
+}
+
+impl ConnectionPool {
+    pub fn new(max_per_host: usize, idle_timeout: Duration) -> ConnectionPool {
+        ConnectionPool { idle: HashMap::new(), max_per_host, idle_timeout }
+    }
+
+    /// Hands out the most recently used live connection to the server, if there is one.
+    /// Anything that's been sitting around longer than the idle timeout gets closed
+    pub fn take(&mut self, scheme: &str, host: &str, port: u16) -> Option<BufReader<Stream>> {
+        let key = (scheme.to_string(), host.to_string(), port);
+        let connections = self.idle.get_mut(&key)?;
+        let idle_timeout = self.idle_timeout;
+        connections.retain(|connection| connection.since.elapsed() < idle_timeout);
+        let connection = connections.pop();
+        if connections.is_empty() {
+            self.idle.remove(&key);
+        }
+        connection.map(|connection| connection.reader)
+    }
+
+    /// Returns a connection to the pool once its response has been read. If the server
+    /// already has as many idle connections as we allow, the oldest one is closed
+    pub fn put(&mut self, scheme: &str, host: &str, port: u16, reader: BufReader<Stream>) {
+        let key = (scheme.to_string(), host.to_string(), port);
+        let connections = self.idle.entry(key).or_default();
+        connections.push(IdleConnection { reader, since: Instant::now() });
+        if connections.len() > self.max_per_host {
+            connections.remove(0);
+        }
+    }
+}
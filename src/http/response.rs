@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Read};
+
+use super::FetchError;
+
+/// A parsed HTTP/1.1 response
+#[derive(Clone)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    /// Header names are lowercased, repeated headers are joined with ", ".
+    /// Set-Cookie is the exception, cookie dates have commas in them so each one goes
+    /// on its own line
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    /// Whether the connection can carry another request after this one
+    pub persistent: bool,
+}
+
+/// Reads one response off the connection, stopping at the end of the message rather
+/// than the end of the stream.
+///
+/// The body length is worked out the way RFC 7230 section 3.3.3 describes: no body for
+/// 1xx/204/304 or HEAD, chunked if the Transfer-Encoding says so, Content-Length bytes
+/// if we're told how many, and otherwise everything until the server hangs up
+pub fn read_response<R: BufRead>(reader: &mut R, head_request: bool) -> Result<Response, FetchError> {
+    loop {
+        let (version, status, reason) = read_status_line(reader)?;
+        let mut headers = read_headers(reader)?;
+
+        // 100 Continue and friends are followed by the real response
+        if (100..200).contains(&status) && status != 101 {
+            continue;
+        }
+
+        let mut read_to_close = false;
+        let body = if head_request || status == 204 || status == 304 || (100..200).contains(&status) {
+            Vec::new()
+        } else if is_chunked(&headers) {
+            read_chunked(reader, &mut headers)?
+        } else if let Some(length) = headers.get("content-length") {
+            let length = parse_content_length(length)?;
+            read_exact_body(reader, length)?
+        } else {
+            read_to_close = true;
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body).map_err(|e| match e.kind() {
+                // Only TLS says this, when the server hangs up without close_notify. With
+                // nothing else marking the end of the body, it may have been cut short
+                io::ErrorKind::UnexpectedEof => {
+                    FetchError::MalformedResponse("connection closed before the end of the body".to_string())
+                },
+                _ => FetchError::from(e),
+            })?;
+            body
+        };
+
+        // HTTP/1.1 keeps the connection open unless told otherwise, 1.0 only if asked to
+        let connection = headers.get("connection").map(|c| c.to_lowercase()).unwrap_or_default();
+        let has_token = |token: &str| connection.split(',').any(|t| t.trim() == token);
+        let persistent = !read_to_close && !has_token("close")
+            && (version == "HTTP/1.1" || has_token("keep-alive"));
+
+        return Ok(Response { status, reason, headers, body, persistent });
+    }
+}
+
+/// TLS reports a server hanging up without close_notify as UnexpectedEof, keeping what
+/// it read before that. Where we know how much should be coming, a short read gets
+/// caught anyway, so there it can be taken as the plain end of the stream
+fn end_at_eof(result: io::Result<usize>) -> io::Result<usize> {
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+        other => other,
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, FetchError> {
+    let mut line = Vec::new();
+    end_at_eof(reader.read_until(b'\n', &mut line))?;
+    if !line.ends_with(b"\n") {
+        return Err(FetchError::MalformedResponse("connection closed in the middle of the headers".to_string()));
+    }
+    // Header bytes outside ASCII aren't meaningful to us, so lossy is fine here
+    let line = String::from_utf8_lossy(&line);
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_status_line<R: BufRead>(reader: &mut R) -> Result<(String, u16, String), FetchError> {
+    let line = read_line(reader)?;
+    let http_status_line: Vec<_> = line.splitn(3, ' ').collect();
+    if http_status_line.len() < 2 || !http_status_line[0].starts_with("HTTP/") {
+        return Err(FetchError::MalformedResponse(format!("bad status line {:?}", line)));
+    }
+    let status: u16 = match http_status_line[1].parse() {
+        Ok(status) => status,
+        Err(_) => return Err(FetchError::MalformedResponse(format!("bad status {:?}", http_status_line[1]))),
+    };
+    let reason = http_status_line.get(2).unwrap_or(&"").to_string();
+    Ok((http_status_line[0].to_string(), status, reason))
+}
+
+/// Reads header lines up to and including the blank line that ends them
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<HashMap<String, String>, FetchError> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        let header_line: Vec<_> = line.splitn(2, ':').collect();
+        if header_line.len() < 2 {
+            return Err(FetchError::MalformedResponse(format!("bad header {:?}", line)));
+        }
+        // values keep their case, a Location path is case sensitive
+        let name = header_line[0].trim().to_lowercase();
+        let value = header_line[1].trim();
+        let separator = if name == "set-cookie" { "\n" } else { ", " };
+        headers.entry(name)
+            .and_modify(|existing| {
+                existing.push_str(separator);
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+}
+
+fn is_chunked(headers: &HashMap<String, String>) -> bool {
+    match headers.get("transfer-encoding") {
+        // chunked has to be the last coding applied
+        Some(encoding) => encoding.rsplit(',').next().unwrap().trim().eq_ignore_ascii_case("chunked"),
+        None => false,
+    }
+}
+
+fn parse_content_length(length: &str) -> Result<u64, FetchError> {
+    // A repeated header gets joined up, which is only allowed if every copy agrees
+    let mut lengths = length.split(',').map(|l| l.trim());
+    let first = lengths.next().unwrap();
+    if lengths.any(|l| l != first) {
+        return Err(FetchError::MalformedResponse(format!("conflicting content lengths {:?}", length)));
+    }
+    first.parse().map_err(|_| FetchError::MalformedResponse(format!("bad content length {:?}", length)))
+}
+
+fn read_exact_body<R: BufRead>(reader: &mut R, length: u64) -> Result<Vec<u8>, FetchError> {
+    // read through take() rather than allocating up front, the length came from the server
+    let mut body = Vec::new();
+    end_at_eof(reader.by_ref().take(length).read_to_end(&mut body))?;
+    if (body.len() as u64) < length {
+        return Err(FetchError::MalformedResponse("body ended early".to_string()));
+    }
+    Ok(body)
+}
+
+/// Decodes a chunked body. Any trailer fields after the last chunk are added to the headers
+fn read_chunked<R: BufRead>(reader: &mut R, headers: &mut HashMap<String, String>) -> Result<Vec<u8>, FetchError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        // the size can be followed by ;extensions, which we don't use
+        let size = line.split(';').next().unwrap().trim();
+        let size = match u64::from_str_radix(size, 16) {
+            Ok(size) => size,
+            Err(_) => return Err(FetchError::MalformedResponse(format!("bad chunk size {:?}", line))),
+        };
+        if size == 0 {
+            break;
+        }
+
+        body.append(&mut read_exact_body(reader, size)?);
+        if !read_line(reader)?.is_empty() {
+            return Err(FetchError::MalformedResponse("chunk longer than its size".to_string()));
+        }
+    }
+
+    for (name, value) in read_headers(reader)? {
+        headers.entry(name).or_insert(value);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(response: &str) -> Result<Response, FetchError> {
+        read_response(&mut response.as_bytes(), false)
+    }
+
+    #[test]
+    fn reads_a_content_length_body_and_leaves_the_rest() {
+        let mut stream: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-A: 1\r\nx-a: 2\r\n\r\nhelloHTTP/1.1";
+        let response = read_response(&mut stream, false).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.reason, "OK");
+        assert_eq!(response.body, b"hello");
+        assert_eq!(response.headers["x-a"], "1, 2");
+        assert!(response.persistent);
+        assert_eq!(stream, b"HTTP/1.1");
+    }
+
+    #[test]
+    fn reads_a_chunked_body_with_trailers() {
+        let response = read("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                             5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: yes\r\n\r\n").unwrap();
+        assert_eq!(response.body, b"hello world");
+        assert_eq!(response.headers["x-trailer"], "yes");
+    }
+
+    #[test]
+    fn reads_to_close_without_a_length() {
+        let response = read("HTTP/1.0 200 OK\r\n\r\nall of it").unwrap();
+        assert_eq!(response.body, b"all of it");
+        assert!(!response.persistent);
+    }
+
+    #[test]
+    fn skips_interim_responses_and_bodies_that_cant_exist() {
+        let response = read("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\nContent-Length: 3\r\n\r\n").unwrap();
+        assert_eq!(response.status, 204);
+        assert!(response.body.is_empty());
+
+        let mut stream: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n";
+        assert!(read_response(&mut stream, true).unwrap().body.is_empty());
+    }
+
+    #[test]
+    fn keeps_set_cookie_headers_apart() {
+        let response = read("HTTP/1.1 200 OK\r\nSet-Cookie: a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\n\
+                             Set-Cookie: b=2\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert_eq!(response.headers["set-cookie"], "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\nb=2");
+    }
+
+    #[test]
+    fn connection_close_ends_keep_alive() {
+        let response = read("HTTP/1.1 200 OK\r\nConnection: Keep-Alive, close\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert!(!response.persistent);
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        for bad in ["HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort",
+                    "HTTP/1.1 200 OK\r\nContent-Length: 1, 2\r\n\r\nab",
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+                    "HTTP/1.1 200 OK\r\nno colon\r\n\r\n",
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n",
+                    "SSH-2.0-OpenSSH\r\n"] {
+            match read(bad) {
+                Err(FetchError::MalformedResponse(_)) => (),
+                other => panic!("{:?} gave {:?}", bad, other.map(|r| r.status)),
+            }
+        }
+    }
+}
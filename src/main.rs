@@ -6,41 +6,79 @@ extern crate conrod_winit;
 extern crate find_folder;
 extern crate glium;
 extern crate image;
-extern crate regex;
 extern crate petgraph;
+extern crate rustls;
+extern crate webpki_roots;
+extern crate flate2;
+extern crate brotli_decompressor;
+extern crate encoding_rs;
+extern crate httpdate;
+extern crate url;
+extern crate data_url;
+extern crate percent_encoding;
+extern crate base64;
 
 
 use std::env;
 use std::process::exit;
-use std::net::TcpStream;
-use std::io::{Write, Read};
+use std::io::{Write, BufReader};
 use std::collections::HashMap;
-use regex::Regex;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use html::Token;
+use url::Url;
 use glium::Surface;
-use conrod_core::{color, widget, Colorable, Widget, Positionable, Sizeable};
+use conrod_core::{color, widget, Borderable, Colorable, Widget, Positionable, Sizeable};
 //Trees, who knew right?
 // linked trees are pretty hard due to rust's guarantees
 use petgraph::Graph;
 
 //conrod support functions
 mod support;
+mod config;
+mod http;
+mod file;
+mod data;
+mod about;
+mod html;
 
 const SCROLL_STEP: f64 = 20.0;
 const INIT_WIDTH: f64 = 800.0;
 const INIT_HEIGHT: f64 = 600.0;
 const FONT_SIZE: u32 = 16;
 const LINE_SPACING: f64 = 1.2;
+/// Elements that never have contents, so there's no end tag to wait for
+const VOID_ELEMENTS: [&str; 13] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+/// The Content-Type of a submitted form
+const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+/// Space between the edge of a form control and the text in it
+const CONTROL_PADDING: f64 = 4.0;
+/// Most idle connections kept open to any one server
+const POOL_MAX_PER_HOST: usize = 6;
+/// How long an idle connection is kept before we assume the server has dropped it
+const POOL_IDLE_TIMEOUT_SECS: u64 = 30;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Must provide one and only one url");
-    }
-    let url = &args[1].as_str();
+    let config = config::Config::from_args(args);
+
+    let mut tundra = Tundra::new(&config);
 
-    let mut tundra = Tundra::new();
+    tundra.browse(&config.url);
+}
+
+/// A timeout setting in seconds, where 0 means no limit
+fn seconds(seconds: usize) -> Option<Duration> {
+    match seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds as u64)),
+    }
+}
 
-    tundra.browse(url);
+/// Escapes text so it can be put into generated HTML, in text or in an attribute value
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
 }
 
 struct WindowUi {
@@ -51,10 +89,23 @@ struct WindowUi {
     font_b: conrod_core::text::font::Id,
     font_i: conrod_core::text::font::Id,
     font_bi: conrod_core::text::font::Id,
+    font_mono: conrod_core::text::font::Id,
 }
 
 enum DisplayListItem {
     Text(DisplayListText),
+    Control(DisplayListControl),
+    Link(DisplayListLink),
+}
+
+impl DisplayListItem {
+    fn y(&self) -> f64 {
+        match self {
+            DisplayListItem::Text(item) => item.y,
+            DisplayListItem::Control(item) => item.y,
+            DisplayListItem::Link(item) => item.y,
+        }
+    }
 }
 
 struct DisplayListText {
@@ -66,6 +117,78 @@ struct DisplayListText {
     underline: bool,
 }
 
+/// The clickable area over a word of link text. It isn't drawn, the text under it is
+struct DisplayListLink {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+    /// The href as written in the page, resolved when it's clicked
+    href: String,
+}
+
+/// A form control's box, with the text that goes in it. node is the control's element,
+/// so a click on the box can find its way back to it
+struct DisplayListControl {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+    text: String,
+    font: conrod_core::text::font::Id,
+    fill: conrod_core::color::Color,
+    border: conrod_core::color::Color,
+    node: petgraph::graph::NodeIndex,
+}
+
+/// The kinds of form control, which decide how they're drawn and what clicking them does
+#[derive(Clone, Copy, PartialEq)]
+enum ControlKind {
+    Text,
+    Password,
+    Checkbox,
+    Radio,
+    Submit,
+    Reset,
+    /// A button that doesn't do anything without scripts
+    Button,
+    Select,
+    TextArea,
+    /// Not shown, but still sent with the form
+    Hidden,
+}
+
+/// What the user has done to the page's form controls. The page's own attributes stay as
+/// they were, so a reset only has to forget what's in here
+#[derive(Default)]
+struct FormState {
+    /// What's been typed into text inputs and textareas
+    values: HashMap<petgraph::graph::NodeIndex, String>,
+    checked: HashMap<petgraph::graph::NodeIndex, bool>,
+    /// Which option each select is on, counting from 0
+    selected: HashMap<petgraph::graph::NodeIndex, usize>,
+    /// The control being typed into
+    focus: Option<petgraph::graph::NodeIndex>,
+}
+
+/// Somewhere to go next: an address, and the form to POST there if there is one
+struct Navigation {
+    url: String,
+    post_body: Option<Vec<u8>>,
+}
+
+/// A page we've been on and can go back (or forward) to, and how far down it we were
+struct HistoryEntry {
+    url: String,
+    scroll_y: f64,
+}
+
+#[derive(Clone, Copy)]
+enum HistoryStep {
+    Back,
+    Forward,
+}
+
 struct ElementNode {
     tag: String,
     attributes: HashMap<String, String>,
@@ -79,6 +202,46 @@ impl ElementNode {
     fn add_attr(&mut self, k: String, v: String) {
         self.attributes.insert(k, v);
     }
+
+    /// An attribute's value. A boolean attribute like disabled has an empty one
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// The names a #fragment can use to point at this element: its id, and for the old
+    /// style <a name> anchors, the name
+    fn anchor_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.attr("id").into_iter().map(str::to_string).collect();
+        if self.tag == "a" {
+            names.extend(self.attr("name").map(str::to_string));
+        }
+        names
+    }
+
+    /// The kind of form control this is, if it is one
+    fn control_kind(&self) -> Option<ControlKind> {
+        match self.tag.as_str() {
+            "input" => Some(match self.attr("type").unwrap_or("text").to_lowercase().as_str() {
+                "password" => ControlKind::Password,
+                "checkbox" => ControlKind::Checkbox,
+                "radio" => ControlKind::Radio,
+                "submit" | "image" => ControlKind::Submit,
+                "reset" => ControlKind::Reset,
+                "button" => ControlKind::Button,
+                "hidden" => ControlKind::Hidden,
+                // search, email, number and so on are all edited as plain text here
+                _ => ControlKind::Text,
+            }),
+            "button" => Some(match self.attr("type").unwrap_or("submit").to_lowercase().as_str() {
+                "reset" => ControlKind::Reset,
+                "button" => ControlKind::Button,
+                _ => ControlKind::Submit,
+            }),
+            "select" => Some(ControlKind::Select),
+            "textarea" => Some(ControlKind::TextArea),
+            _ => None,
+        }
+    }
 }
 
 struct TextNode {
@@ -106,12 +269,47 @@ struct LayoutState{
     italic: bool,
     terminal_space: bool,
     in_body: bool,
-    in_comment: bool,
     underline: bool,
-
+    /// The href of the link we're inside, if any
+    link: Option<String>,
+    /// How far down the page each element with an id (or <a> with a name) starts
+    anchors: HashMap<String, f64>,
 }
 
 impl LayoutState {
+    fn new() -> LayoutState {
+        LayoutState {
+            layout_tree: Graph::<LayoutNode, i32>::new(),
+            layout_root: None,
+            x: 13.0,
+            y: 13.0,
+            current_color: conrod_core::color::BLACK,
+            bold: false,
+            italic: false,
+            terminal_space: true,
+            in_body: false,
+            underline: false,
+            link: None,
+            anchors: HashMap::new(),
+        }
+    }
+
+    /// Notes where an element starts, for #fragments to find. If two share a name, the
+    /// first one wins
+    fn add_anchors(&mut self, names: Vec<String>) {
+        for name in names {
+            let y = self.y;
+            self.anchors.entry(name).or_insert(y);
+        }
+    }
+
+    /// Puts the pen back at the top of the page, ready to lay the same tree out again
+    fn restart(&mut self) {
+        let layout_tree = std::mem::replace(&mut self.layout_tree, Graph::new());
+        let layout_root = self.layout_root;
+        *self = LayoutState { layout_tree, layout_root, ..LayoutState::new() };
+    }
+
     /// Adds a node into the tree and returns it's index. Useful for setting up a root node. Use add_child to add a node and link to it
     fn add_node(&mut self, node: LayoutNode) -> petgraph::graph::NodeIndex {
         self.layout_tree.add_node(node)
@@ -147,11 +345,47 @@ impl LayoutState {
     fn get_layout_node(&mut self, node_index: petgraph::graph::NodeIndex) -> &LayoutNode {
         &self.layout_tree[node_index]
     }
-}
 
-enum Token {
-    Text(String),
-    Tag(String),
+    fn element(&self, node: petgraph::graph::NodeIndex) -> Option<&ElementNode> {
+        match self.layout_tree[node] {
+            LayoutNode::Element(ref element) => Some(element),
+            LayoutNode::Text(_) => None,
+        }
+    }
+
+    /// Everything under a node, in document order
+    fn descendants(&self, node: petgraph::graph::NodeIndex) -> Vec<petgraph::graph::NodeIndex> {
+        let mut descendants = Vec::new();
+        let mut children: Vec<_> = self.layout_tree.neighbors_directed(node, petgraph::Outgoing).collect();
+        // neighbors come newest first, which is the reverse of how they were in the page
+        children.reverse();
+        for child in children {
+            descendants.push(child);
+            descendants.extend(self.descendants(child));
+        }
+        descendants
+    }
+
+    /// All the text under a node, joined up
+    fn text_content(&self, node: petgraph::graph::NodeIndex) -> String {
+        self.descendants(node).into_iter()
+            .filter_map(|node| match self.layout_tree[node] {
+                LayoutNode::Text(ref text) => Some(text.text.as_str()),
+                LayoutNode::Element(_) => None,
+            })
+            .collect()
+    }
+
+    /// The closest element above node with the given tag
+    fn ancestor(&self, node: petgraph::graph::NodeIndex, tag: &str) -> Option<petgraph::graph::NodeIndex> {
+        let mut node = node;
+        loop {
+            node = self.layout_tree.neighbors_directed(node, petgraph::Incoming).next()?;
+            if self.element(node).is_some_and(|element| element.tag == tag) {
+                return Some(node);
+            }
+        }
+    }
 }
 
 struct Tundra {
@@ -160,31 +394,81 @@ struct Tundra {
     scroll_y : f64,
     tokens: Vec<Token>,
     display_list: Vec<DisplayListItem>,
-    layout_state: LayoutState
+    layout_state: LayoutState,
+    tls_config: Arc<rustls::ClientConfig>,
+    connection_pool: http::ConnectionPool,
+    cache: http::Cache,
+    cookie_jar: http::CookieJar,
+    max_redirects: usize,
+    timeouts: http::Timeouts,
+    /// When the fetch in progress has to be finished by
+    deadline: Option<Instant>,
+    /// The page whose link or form started the fetch in progress, which decides the
+    /// SameSite cookies it gets. None when the user typed the address or went back to it
+    initiator: Option<Url>,
+    proxies: http::Proxies,
+    /// The settings we were started with, for about:config
+    config: config::Config,
+    /// Every page shown this session, oldest first, for about:history
+    history: Vec<Url>,
+    /// The address of the page being shown, after any redirects
+    url: Url,
+    /// The raw page source, when we're showing a view-source: page
+    view_source: Option<String>,
+    form_state: FormState,
+    /// Pages to go back to, most recent last
+    back: Vec<HistoryEntry>,
+    /// Pages we've gone back from, most recent last
+    forward: Vec<HistoryEntry>,
 }
 
 impl Tundra {
 
-    fn new() -> Tundra {
+    fn new(config: &config::Config) -> Tundra {
+        let tls_config = match http::tls_config(config.ca_file.as_deref()) {
+            Ok(tls_config) => tls_config,
+            Err(e) => {
+                println!("Failed to load certificates: {}", e);
+                exit(1);
+            }
+        };
+        let proxies = match http::Proxies::new(config.http_proxy.as_deref(), config.https_proxy.as_deref(),
+                                               config.no_proxy.as_deref()) {
+            Ok(proxies) => proxies,
+            Err(e) => {
+                println!("Failed to set up the proxy: {}", e);
+                exit(1);
+            }
+        };
+
         return Tundra {
             window_height: INIT_HEIGHT,
             window_width: INIT_WIDTH,
             scroll_y: 0.0,
             tokens: Vec::new(),
             display_list: Vec::new(),
-            layout_state: LayoutState {
-                layout_tree: Graph::<LayoutNode, i32>::new(),
-                layout_root: None,
-                x: 13.0,
-                y: 13.0,
-                current_color: conrod_core::color::BLACK,
-                bold: false,
-                italic: false,
-                terminal_space: true,
-                in_body: false,
-                in_comment: false,
-                underline: false
-            }
+            layout_state: LayoutState::new(),
+            tls_config,
+            connection_pool: http::ConnectionPool::new(POOL_MAX_PER_HOST,
+                                                       Duration::from_secs(POOL_IDLE_TIMEOUT_SECS)),
+            cache: http::Cache::new(config.cache_dir.as_ref().map(PathBuf::from)),
+            cookie_jar: http::CookieJar::new(config.cookie_file.as_ref().map(PathBuf::from)),
+            max_redirects: config.max_redirects,
+            timeouts: http::Timeouts {
+                connect: seconds(config.connect_timeout),
+                read: seconds(config.read_timeout),
+                total: seconds(config.timeout),
+            },
+            deadline: None,
+            initiator: None,
+            proxies,
+            config: config.clone(),
+            history: Vec::new(),
+            url: Url::parse("about:blank").unwrap(),
+            view_source: None,
+            form_state: FormState::default(),
+            back: Vec::new(),
+            forward: Vec::new(),
         };
     }
     /// A convenience method that combines all of the steps for the browser to
@@ -197,205 +481,463 @@ impl Tundra {
         // construct our `Ui`.
         let mut window_ui = self.set_up_window();
 
-        let (host, port, path, _fragment) = self.parse_address(url);
-        let (_headers, body) = self.request(&host, &port, &path);
-        // test case for spaces and bounding rects being applied correctly
-        //   correct: tight boxes and a proper space. incorrect: extra space in the boxes and overlap
-        //let body = "<p>aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa a</p>".to_string();
-//        let body = "<body><p>1</p> <p>2</p> <p>3</p></body>".to_string();
-        self.lex(body);
-        self.parse_tokens();
-        self.layout(&mut window_ui, self.layout_state.layout_root.unwrap());
+        self.load_page(&mut window_ui, url, None);
 
         self.render(&mut window_ui);
     }
 
-    fn parse_address(&self, url: &str) -> (String, String, String, String) {
-        if !url.starts_with("http://") {
-            panic!("Tundra only supports http");
+    /// Goes to a new page, leaving the current one on the back stack. A link to a
+    /// #fragment of the page we're already on just scrolls to it
+    fn navigate(&mut self, window_ui: &mut WindowUi, navigation: Navigation) {
+        let current = self.history_entry();
+        self.back.push(current);
+        self.forward.clear();
+        match self.same_page(&navigation.url) {
+            Some(url) if url.fragment().is_some() && navigation.post_body.is_none() => {
+                self.url = url;
+                self.scroll_to_fragment();
+            },
+            _ => {
+                self.initiator = Some(self.url.clone());
+                self.load_page(window_ui, &navigation.url, navigation.post_body);
+                self.initiator = None;
+            },
         }
+    }
 
-        let scheme_rest: Vec<_> = url.split("://").collect();
-        let _scheme: &str = &scheme_rest[0];
-        let rest: &str = &scheme_rest[1];
-
-        let mut hostport = rest;
-        let mut pathfragment = "/";
-        if rest.contains("/") {
-            let address: Vec<_> = rest.splitn(2, "/").collect();
-            hostport = address[0];
-            pathfragment = address[1];
+    /// Goes back or forward a page, to where we were scrolled to when we left it.
+    /// A page that came from a form is fetched again without the form
+    fn step_history(&mut self, window_ui: &mut WindowUi, step: HistoryStep) {
+        let current = self.history_entry();
+        let (from, to) = match step {
+            HistoryStep::Back => (&mut self.back, &mut self.forward),
+            HistoryStep::Forward => (&mut self.forward, &mut self.back),
+        };
+        let entry = match from.pop() {
+            Some(entry) => entry,
+            None => return,
+        };
+        to.push(current);
+        match self.same_page(&entry.url) {
+            Some(url) => self.url = url,
+            None => self.load_page(window_ui, &entry.url, None),
         }
+        self.scroll_y = entry.scroll_y;
+    }
 
-        let mut host: String = hostport.to_string();
-        let mut port: String = "80".to_string();
-        if hostport.contains(":") {
-            let hostport_vec: Vec<_> = hostport.rsplitn(2, ":").collect();
-            host = hostport_vec[1].to_string();
-            port = hostport_vec[0].to_string();
+    /// url, if it's the page we're on apart from the #fragment
+    fn same_page(&self, url: &str) -> Option<Url> {
+        let url = Url::parse(url).ok()?;
+        if url[..url::Position::AfterQuery] == self.url[..url::Position::AfterQuery] {
+            Some(url)
+        } else {
+            None
         }
+    }
+
+    /// Scrolls to the element the url's #fragment names, going by its id or <a name>.
+    /// An empty fragment or #top means the top of the page, and one that doesn't match
+    /// anything leaves the page where it is
+    fn scroll_to_fragment(&mut self) {
+        let fragment = match self.url.fragment() {
+            Some(fragment) => fragment.to_string(),
+            None => return,
+        };
+        let decoded = percent_encoding::percent_decode_str(&fragment).decode_utf8_lossy().to_string();
+        let anchors = &self.layout_state.anchors;
+        let y = match anchors.get(&fragment).or_else(|| anchors.get(&decoded)) {
+            // leave the same margin above it as the top of the page has
+            Some(y) => y - 13.0,
+            None if fragment.is_empty() || decoded.eq_ignore_ascii_case("top") => 0.0,
+            None => return,
+        };
+        // no further than scrolling down would go
+        let bottom = self.display_list.last().map_or(0.0, |item| item.y() - self.window_height);
+        self.scroll_y = y.min(bottom).max(0.0);
+    }
+
+    fn history_entry(&self) -> HistoryEntry {
+        HistoryEntry { url: self.url.to_string(), scroll_y: self.scroll_y }
+    }
 
-        let mut path: String = "/".to_string() + pathfragment;
-        let mut fragment: String = "".to_string();
-        if pathfragment.contains("#") {
-            let pathfragment_vec: Vec<_> = pathfragment.rsplitn(2, "#").collect();
-            path = "/".to_string() + pathfragment_vec[1];
-            fragment = "#".to_string() + pathfragment_vec[0];
+    /// Replaces whatever page is showing with the one at url, starting again from a fresh
+    /// layout at the top of the page. With a post_body the page is asked for with a POST
+    fn load_page(&mut self, window_ui: &mut WindowUi, url: &str, post_body: Option<Vec<u8>>) {
+        self.layout_state = LayoutState::new();
+        self.display_list.clear();
+        self.form_state = FormState::default();
+        self.view_source = None;
+        self.scroll_y = 0.0;
+
+        if let Some(source_url) = url.strip_prefix("view-source:") {
+            self.browse_source(window_ui, source_url);
+            return;
         }
 
-        return (host, port, path, fragment)
+        let body = match self.parse_address(url).and_then(|url| self.fetch_with(url, post_body)) {
+            Ok((headers, body)) => {
+                // anything that isn't HTML is shown as plain text
+                match headers.get("content-type") {
+                    Some(content_type) if !content_type.to_lowercase().contains("html") => self.text_page(&body),
+                    _ => body,
+                }
+            },
+            Err(e) => {
+                if let Ok(url) = self.parse_address(url) {
+                    self.url = url;
+                }
+                self.error_page(url, &e)
+            }
+        };
+        let title = format!("Tundra - {}", self.url);
+        window_ui.display.0.gl_window().window().set_title(&title);
+        // test case for spaces and bounding rects being applied correctly
+        //   correct: tight boxes and a proper space. incorrect: extra space in the boxes and overlap
+        //let body = "<p>aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa a</p>".to_string();
+//        let body = "<body><p>1</p> <p>2</p> <p>3</p></body>".to_string();
+        self.lex(body);
+        self.parse_tokens();
+        // an empty body, or one that's only a doctype or comments, has nothing to lay out
+        if let Some(root) = self.layout_state.layout_root {
+            self.layout(window_ui, root);
+        }
+        self.scroll_to_fragment();
     }
 
-    fn request(&self, host: &str, port: &str, path: &str) -> (HashMap<String, String>, String) {
-        let address = format!("{}:{}", host, port);
-        match TcpStream::connect(address) {
-            Ok(mut socket) => {
-                let request_string = format!("GET {} HTTP/1.1\r\n\
-                                          Host: {}\r\n\
-                                          User-Agent: HeliosPanoptes\r\n\
-                                          Connection: close\r\n\r\n", path, host);
+    /// The view-source: version of browse(). The page is fetched as usual, but its body is
+    /// shown as it came, rather than being lexed and laid out
+    fn browse_source(&mut self, window_ui: &mut WindowUi, url: &str) {
+        let source = match self.parse_address(url).and_then(|url| self.fetch(url)) {
+            Ok((_headers, body)) => body,
+            Err(e) => {
+                if let Ok(url) = self.parse_address(url) {
+                    self.url = url;
+                }
+                self.error_page(url, &e)
+            }
+        };
+        // show the address we really got the source from, after redirects
+        if let Ok(source_url) = Url::parse(&format!("view-source:{}", self.url)) {
+            self.url = source_url;
+        }
+        let title = format!("Tundra - {}", self.url);
+        window_ui.display.0.gl_window().window().set_title(&title);
 
-                socket.write(request_string.as_bytes()).unwrap();
+        self.layout_source(window_ui, &source);
+        self.view_source = Some(source);
+    }
 
-                let mut buf = Vec::new();
+    /// Requests the page at a url, following redirects until we land on the real thing.
+    /// The final url ends up in self.url, since that's what the page's relative links are
+    /// relative to
+    ///
+    /// All of it, redirects included, has to finish within the total timeout
+    fn fetch(&mut self, url: Url) -> Result<(HashMap<String, String>, String), http::FetchError> {
+        self.fetch_with(url, None)
+    }
 
-                match socket.read_to_end(&mut buf) {
-                    Ok(_) => {
-                        let response = String::from_utf8_lossy(&buf);
+    /// fetch(), but POSTing post_body as a urlencoded form if there is one
+    fn fetch_with(&mut self, url: Url, post_body: Option<Vec<u8>>)
+                  -> Result<(HashMap<String, String>, String), http::FetchError> {
+        self.deadline = self.timeouts.total.map(|total| Instant::now() + total);
+        let result = self.follow_redirects(url, post_body);
+        let deadline_passed = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        self.deadline = None;
+        match (result, self.timeouts.total) {
+            // whatever timed out, it was the deadline that cut it short
+            (Err(http::FetchError::Timeout(_)), Some(total)) if deadline_passed => {
+                Err(http::FetchError::Timeout(http::TimeoutKind::Total(total)))
+            },
+            (result, _) => result,
+        }
+    }
 
-                        let response_vec: Vec<_> = response.split("\r\n\r\n").collect();
-                        let raw_headers: String = response_vec[0].to_string();
-                        let body: String = response_vec[1].to_string();
+    fn follow_redirects(&mut self, url: Url, post_body: Option<Vec<u8>>)
+                        -> Result<(HashMap<String, String>, String), http::FetchError> {
+        let mut url = url;
+        let mut post_body = post_body;
+        let mut redirects = 0;
+        // The requests made since the last Set-Cookie, and whether each was a POST. Making
+        // one of them again would only get the same answer, but once a cookie has been
+        // set the server might answer differently, like a login page sending us back
+        let mut visited: Vec<(Url, bool)> = Vec::new();
+        loop {
+            let posted = post_body.is_some();
+            let response = match (url.scheme(), &post_body) {
+                ("http" | "https", Some(body)) => {
+                    let content_type = [("Content-Type", FORM_URLENCODED.to_string())];
+                    self.request("POST", &url, Some(body), &content_type)?
+                },
+                ("http" | "https", None) => self.cached_request(&url)?,
+                // anything else just ignores a form posted to it
+                ("file", _) => file::load(&url)?,
+                ("data", _) => data::load(&url)?,
+                ("about", _) => about::load(&url, &self.history, &self.config)?,
+                (scheme, _) => return Err(http::FetchError::UnsupportedScheme(scheme.to_string())),
+            };
+
+            if response.status >= 400 {
+                return Err(http::FetchError::Http(response.status, response.reason));
+            }
+            let location = match response.headers.get("location") {
+                Some(location) if [301, 302, 303, 307, 308].contains(&response.status) => location,
+                _ => {
+                    self.history.push(url.clone());
+                    self.url = url;
+                    let body = http::decode_text(&response.headers, &response.body);
+                    return Ok((response.headers, body));
+                }
+            };
+            let mut next = match url.join(location) {
+                Ok(next) => next,
+                Err(_) => return Err(http::FetchError::BadUrl(location.to_string())),
+            };
+            // a redirect without its own fragment keeps the one we asked for
+            if next.fragment().is_none() {
+                next.set_fragment(url.fragment());
+            }
+            // only 307 and 308 send the form on again, the rest turn a POST into a GET
+            if ![307, 308].contains(&response.status) {
+                post_body = None;
+            }
 
-                        // split the headers into lines
-                        let mut header_lines: Vec<_> = raw_headers.split("\r\n").collect();
-                        // parse the http status line
-                        let http_status_line: Vec<_> = header_lines[0].splitn(3, " ").collect();
-                        let _version = http_status_line[0];
-                        let status = http_status_line[1];
-                        let explanation = http_status_line[2];
-                        assert!(status == "200", format!("Server error{}:{}", status, explanation));
-                        //remove the http status line from the list of headers
-                        header_lines.remove(0);
+            if response.headers.contains_key("set-cookie") {
+                visited.clear();
+            }
+            visited.push((url, posted));
+            if visited.contains(&(next.clone(), post_body.is_some())) {
+                return Err(http::FetchError::RedirectLoop(next.to_string()));
+            }
+            redirects += 1;
+            if redirects > self.max_redirects {
+                return Err(http::FetchError::TooManyRedirects(self.max_redirects));
+            }
+            url = next;
+        }
+    }
 
-                        let mut headers = HashMap::new();
+    /// Wraps plain text up as a page, so its markup-looking bits show up as text
+    fn text_page(&self, text: &str) -> String {
+        format!("<html><body>{}</body></html>", escape_html(text))
+    }
 
-                        for header in header_lines {
-                            let header_line: Vec<_> = header.splitn(2, ":").collect();
-                            headers.insert(header_line[0].to_string().trim().to_lowercase(),
-                                           header_line[1].to_string().trim().to_lowercase());
-                        };
+    /// Builds the page shown in place of one that couldn't be loaded
+    fn error_page(&self, url: &str, error: &http::FetchError) -> String {
+        format!("<html><body>\
+                 <h1>Tundra can't open this page</h1>\
+                 <p><b>{}</b></p>\
+                 <p>{}</p>\
+                 </body></html>", escape_html(url), escape_html(&error.to_string()))
+    }
 
-                        return (headers, body);
-                    },
-                    Err(_e) => {
-                        println!("Failed to receive data");
-                        exit(1);
-                    }
+    /// Parses an absolute url the way the WHATWG URL standard does, which also normalizes
+    /// it: lowercased scheme and host, default ports dropped, dot segments resolved and
+    /// anything that needs it percent-encoded. Relative urls go through Url::join instead
+    ///
+    /// Something that isn't a url is taken to be a path relative to where Tundra was
+    /// started, and opened as a file:// url, so `tundra page.html` works
+    fn parse_address(&self, url: &str) -> Result<Url, http::FetchError> {
+        match Url::parse(url) {
+            Ok(url) => Ok(url),
+            Err(_) => {
+                let path = env::current_dir().map(|dir| dir.join(url));
+                match path.map(Url::from_file_path) {
+                    // going through parse() again collapses any .. in the path
+                    Ok(Ok(file_url)) => Url::parse(file_url.as_str())
+                        .map_err(|_| http::FetchError::BadUrl(url.to_string())),
+                    _ => Err(http::FetchError::BadUrl(url.to_string())),
                 }
+            },
+        }
+    }
+
+    /// Puts the cache in front of request(). Fresh copies are used as they are, stale ones
+    /// are revalidated with the server, and a 304 answer means the copy is good to use
+    fn cached_request(&mut self, url: &Url) -> Result<http::Response, http::FetchError> {
+        // the fragment never reaches the server, so it isn't part of the key
+        let cache_key = &url[..url::Position::AfterQuery];
+        let mut validators = Vec::new();
+        if let Some(entry) = self.cache.get(cache_key) {
+            if entry.is_fresh() {
+                return Ok(entry.response.clone());
             }
-            Err(_e) => {
-                println!("Failed to connect to url");
-                exit(1);
+            validators = entry.validators();
+        }
+
+        let response = self.request("GET", url, None, &validators)?;
+        if response.status == 304 {
+            if let Some(cached) = self.cache.freshen(cache_key, &response) {
+                return Ok(cached);
             }
-        };
+        }
+        self.cache.store(cache_key, &response);
+        Ok(response)
     }
 
-    fn lex(&mut self, source: String) {
-        let mut tokens: Vec<Token> = Vec::new();
-        let mut text: String = "".to_string();
-        for c in source.chars() {
-            if c == '<' {
-                //store the text so far and reset
-                if !text.is_empty() {
-                    tokens.push(Token::Text(text.to_string()));
+    fn request(&mut self, method: &str, url: &Url, body: Option<&[u8]>, extra_headers: &[(&str, String)])
+               -> Result<http::Response, http::FetchError> {
+        let scheme = url.scheme();
+        // IPv6 addresses are bracketed in urls, but not when we go to connect to them
+        let host = match url.host() {
+            Some(url::Host::Ipv6(address)) => address.to_string(),
+            Some(host) => host.to_string(),
+            None => return Err(http::FetchError::BadUrl(url.to_string())),
+        };
+        let port = match url.port_or_known_default() {
+            Some(port) => port,
+            None => return Err(http::FetchError::BadUrl(url.to_string())),
+        };
+        let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+        // host plus the port, if it isn't the default one
+        let host_header = &url[url::Position::BeforeHost..url::Position::AfterPort];
+        let proxy = self.proxies.for_url(scheme, &host);
+        // an HTTP proxy we're forwarding to needs the whole url to know where to send it
+        let forwarded = proxy.filter(|proxy| proxy.forwards(scheme));
+        let target = match forwarded {
+            Some(_) => &url[..url::Position::AfterQuery],
+            None => path,
+        };
+
+        let mut request_string = format!("{} {} HTTP/1.1\r\n\
+                                      Host: {}\r\n\
+                                      User-Agent: HeliosPanoptes\r\n\
+                                      Accept-Encoding: {}\r\n", method, target, host_header, http::ACCEPT_ENCODING);
+        if let Some(authorization) = forwarded.and_then(|proxy| proxy.authorization()) {
+            request_string.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+        }
+        for (name, value) in extra_headers {
+            request_string.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some(body) = body {
+            request_string.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        if let Some(cookies) = self.cookie_jar.cookie_header(scheme, &host, path, method, self.initiator.as_ref()) {
+            request_string.push_str(&format!("Cookie: {}\r\n", cookies));
+        }
+        request_string.push_str("\r\n");
+        let mut request_bytes = request_string.into_bytes();
+        request_bytes.extend_from_slice(body.unwrap_or(&[]));
+
+        // A pooled connection may have been closed by the server while it sat idle, which we
+        // only find out by trying it. GETs are safe to send again, so retry on a new one.
+        // Anything else might have been acted on already, so it only goes out on a new one
+        let pooled = if method == "GET" { self.connection_pool.take(scheme, &host, port) } else { None };
+        let (reader, mut response) = match pooled {
+            Some(mut reader) => {
+                // it keeps the deadline of whichever fetch last used it
+                reader.get_mut().set_deadline(self.deadline);
+                match self.send(&mut reader, method, &request_bytes) {
+                    Ok(response) => (reader, response),
+                    Err(_) => {
+                        let mut reader = BufReader::new(http::connect(scheme, &host, port, &self.tls_config,
+                                                                      &self.timeouts, self.deadline, proxy)?);
+                        let response = self.send(&mut reader, method, &request_bytes)?;
+                        (reader, response)
+                    },
                 }
-                text = "".to_string();
-            } else if c == '>' {
-                //store the tag and reset
-                tokens.push(Token::Tag(text.to_string()));
-                text = "".to_string();
-            } else {
-                text.push(c);
+            },
+            None => {
+                let mut reader = BufReader::new(http::connect(scheme, &host, port, &self.tls_config,
+                                                              &self.timeouts, self.deadline, proxy)?);
+                let response = self.send(&mut reader, method, &request_bytes)?;
+                (reader, response)
+            },
+        };
+
+        if response.persistent {
+            self.connection_pool.put(scheme, &host, port, reader);
+        }
+        if let Some(set_cookies) = response.headers.get("set-cookie") {
+            for set_cookie in set_cookies.lines() {
+                self.cookie_jar.set_cookie(scheme, &host, path, set_cookie);
             }
+            // losing the saved cookies isn't worth interrupting the page for
+            let _ = self.cookie_jar.save();
         }
-        self.tokens = tokens;
+        http::decode_body(&mut response)?;
+        Ok(response)
+    }
+
+    /// Writes a request out on a connection and reads back the response
+    fn send(&self, reader: &mut BufReader<http::Stream>, method: &str, request: &[u8]) -> Result<http::Response, http::FetchError> {
+        reader.get_mut().write_all(request)?;
+        http::read_response(reader, method == "HEAD")
+    }
+
+    fn lex(&mut self, source: String) {
+        self.tokens = html::tokenize(&source);
     }
 
     /// Put the list of tokens into a layout tree, return the root node
     fn parse_tokens(&mut self) {
         //thumbtack
         let mut current_node: Option<petgraph::graph::NodeIndex> = None;
-        let attribute_re: Regex = Regex::new(r#"((\w+)=((\w+)|(".*?")))+"#).unwrap();
 
         for token in self.tokens.iter() {
             match token {
                 Token::Text(text) => {
-                    let text = self.translate_entities(text.to_owned());
-                    let new_node = LayoutNode::Text(TextNode::new(text));
+                    let new_node = LayoutNode::Text(TextNode::new(text.to_owned()));
                     match current_node {
                         Some(current_node) => { self.layout_state.add_child(current_node, new_node); },
-                        None => { panic!("Tried to add a text node to an empty layout graph"); },
+                        // whitespace before the first tag, like the newline after a doctype
+                        None if text.trim().is_empty() => (),
+                        None => {
+                            // text with no element to go in gets an html element made for it
+                            let root = self.layout_state.add_node(LayoutNode::Element(ElementNode::new("html".to_string())));
+                            self.layout_state.add_child(root, new_node);
+                            current_node = Some(root);
+                        },
                     }
                 },
-                Token::Tag(tag) => {
-                    if !tag.starts_with("/") { //open tag
-                        let self_closing;
-                        if tag.ends_with("/") { self_closing = true; } else { self_closing = false; }
-
-                        //tag name
-                        let tag_parts: Vec<&str> = tag.splitn(2, " ").collect();
-                        let mut tag_name = tag_parts[0].to_string();
-                        if tag_parts.last().unwrap().ends_with("--") {
-                            tag_name.push_str("--"); //handle end comments
-                        }
-                        let mut new_element = ElementNode::new(tag_name.clone());
-                        if tag_parts.len() > 1 {
-                            let attributes = tag_parts[1];
-                            for capture in attribute_re.captures_iter(&attributes) {
-                                //use the capture groups to split the keys and values
-                                let name = capture[2].to_string().to_lowercase();
-                                let value = self.translate_entities(capture[3].to_string());
-                                new_element.add_attr(name, value);
-                            }
-                        }
-                        let new_node = match current_node {
-                            Some(current_node) => { self.layout_state.add_child(current_node, LayoutNode::Element(new_element)) },
-                            None => { self.layout_state.add_node(LayoutNode::Element(new_element)) },
-                        };
-                        //enter the current node, unless it's self-closing
-                        if !(self_closing || ["br", "link", "meta"].contains(&tag_name.as_str())) {
-                            current_node = Some(new_node);
-                        }
-                    } else { //close tag
-                        let tag = &tag[1..];
-                        let mut node = current_node;
-
-                        //travel up the tree until we find the right tag or we go off the end
-                        while !node.is_none() {
-                            match self.layout_state.get_layout_node(node.unwrap()) {
-                                LayoutNode::Element(element) => {
-                                    let node_tag = element.tag.to_string();
-                                    if node_tag != tag.to_string() {
-                                        node = self.layout_state.parent_of(node.unwrap());
-                                    } else {
-                                        break;
-                                    }
-                                },
-                                _ => ()
-                            }
-                        }
+                Token::StartTag { name, attributes, self_closing } => {
+                    let mut new_element = ElementNode::new(name.clone());
+                    for (name, value) in attributes {
+                        new_element.add_attr(name.clone(), value.clone());
+                    }
+                    let new_node = match current_node {
+                        Some(current_node) => { self.layout_state.add_child(current_node, LayoutNode::Element(new_element)) },
+                        None => { self.layout_state.add_node(LayoutNode::Element(new_element)) },
+                    };
+                    //enter the current node, unless it's self-closing
+                    if !(*self_closing || VOID_ELEMENTS.contains(&name.as_str())) {
+                        current_node = Some(new_node);
+                    }
+                },
+                Token::EndTag { name } => {
+                    let tag = name;
+                    let mut node = current_node;
+                    // an end tag before any start tag has nothing to close, and a void
+                    // element was never opened to need one
+                    if node.is_none() || VOID_ELEMENTS.contains(&tag.as_str()) {
+                        continue;
+                    }
 
-                        //Handle mis-closed tags
-                        if node.is_none() && self.layout_state.parent_of(current_node.unwrap()).is_some() {
-                            //If we went off the end of the tree, and our current node has a parent, go up a level
-                            current_node = self.layout_state.parent_of(current_node.unwrap());
-                        } else if self.layout_state.parent_of(node.unwrap()).is_some() {
-                            //If we found the tag because we aren't off the tree, set the current node the parent of the tag node
-                            current_node = self.layout_state.parent_of(node.unwrap());
+                    //travel up the tree until we find the right tag or we go off the end
+                    while !node.is_none() {
+                        match self.layout_state.get_layout_node(node.unwrap()) {
+                            LayoutNode::Element(element) => {
+                                let node_tag = element.tag.to_string();
+                                if node_tag != tag.to_string() {
+                                    node = self.layout_state.parent_of(node.unwrap());
+                                } else {
+                                    break;
+                                }
+                            },
+                            _ => ()
                         }
                     }
-                }
+
+                    //Handle mis-closed tags
+                    if node.is_none() && self.layout_state.parent_of(current_node.unwrap()).is_some() {
+                        //If we went off the end of the tree, and our current node has a parent, go up a level
+                        current_node = self.layout_state.parent_of(current_node.unwrap());
+                    } else if node.is_some() && self.layout_state.parent_of(node.unwrap()).is_some() {
+                        //If we found the tag because we aren't off the tree, set the current node the parent of the tag node
+                        current_node = self.layout_state.parent_of(node.unwrap());
+                    }
+                },
+                // comments never reach the tree, so nothing inside one is laid out
+                Token::Comment(_) | Token::Doctype(_) => (),
             }
         }
 
@@ -410,18 +952,15 @@ impl Tundra {
         self.layout_state.layout_root = current_node;
     }
 
-    fn translate_entities(&self, mut text: String) -> String {
-        //&amp;, &lt;, &gt;, and &quot;
-        text = text.replace("&amp;", "&");
-        text = text.replace("&lt;", "<");
-        text = text.replace("&gt;", ">");
-        text = text.replace("&quot;", "\"");
-        return text;
-    }
-
     /// Takes text and returns a display-list of the format (x, y, text)
     fn layout(&mut self, window_ui: &mut WindowUi, node: petgraph::graph::NodeIndex) {
         match self.layout_state.get_layout_node(node) {
+            // controls draw their contents themselves
+            LayoutNode::Element(element) if element.control_kind().is_some() => {
+                self.layout_control(window_ui, node);
+            },
+            // their text is code, styling or the page's name, not something to show on the page
+            LayoutNode::Element(element) if ["script", "style", "title"].contains(&element.tag.as_str()) => (),
             LayoutNode::Element(_element) => {
                 self.layout_open(window_ui, node);
                 //must detach from the tree in order to walk and mutate state at the same time
@@ -447,17 +986,10 @@ impl Tundra {
                 let linespace_h = w.get_h(&window_ui.ui).unwrap();
 
                 let tag = element.tag.clone();
+                let href = element.attr("href").map(str::to_string);
+                let anchor_names = element.anchor_names();
                 let mut state = &mut self.layout_state;
 
-                //handle comments
-                if tag.starts_with("!--") {
-                    state.in_comment = true;
-                }
-                // Will skip multi-tag comments
-                if state.in_comment {
-                    return;
-                }
-
                 match tag.as_str() {
                     "body" => state.in_body = true,
                     "i" => state.italic = true,
@@ -470,9 +1002,11 @@ impl Tundra {
                     "a" => {
                         state.underline = true;
                         state.current_color = color::BLUE;
+                        state.link = href;
                     },
                     _ => ()
                 }
+                state.add_anchors(anchor_names);
             },
             _ => (panic!("Called layout_open() on something that wasn't an element node type"))
         }
@@ -491,15 +1025,6 @@ impl Tundra {
                 let tag = element.tag.clone();
                 let mut state = &mut self.layout_state;
 
-                if tag.ends_with("--") {
-                    state.in_comment = false;
-                    return;
-                }
-                // Will skip multi-tag comments
-                if state.in_comment {
-                    return;
-                }
-
                 match tag.as_str() {
                     "body" => state.in_body = false,
                     "i" => state.italic = false,
@@ -518,6 +1043,7 @@ impl Tundra {
                     "a" => {
                         state.underline = false;
                         state.current_color = color::BLACK;
+                        state.link = None;
                     },
                     _ => ()
                 }
@@ -585,6 +1111,15 @@ impl Tundra {
                         underline: state.underline
                     };
                     self.display_list.push(DisplayListItem::Text(display_list_text));
+                    if let Some(ref href) = state.link {
+                        self.display_list.push(DisplayListItem::Link(DisplayListLink {
+                            x: state.x,
+                            y: state.y,
+                            w: w_wh[0],
+                            h: w_wh[1],
+                            href: href.clone(),
+                        }));
+                    }
 
                     let mut whitespace = whitespace_w;
                     if i == (wordcount - 1) {
@@ -602,6 +1137,436 @@ impl Tundra {
         }
     }
 
+    /// Lays out a form control as a box in the line of text, sized to fit what's in it
+    fn layout_control(&mut self, window_ui: &mut WindowUi, node: petgraph::graph::NodeIndex) {
+        let state = &self.layout_state;
+        let kind = state.element(node).and_then(|element| element.control_kind()).unwrap();
+        if !state.in_body || kind == ControlKind::Hidden {
+            return;
+        }
+        let element = state.element(node).unwrap();
+        let font = window_ui.font;
+        let measure = |text: &str| {
+            let w = widget::Text::new(text)
+                .font_id(font)
+                .font_size(FONT_SIZE);
+            [w.get_w(&window_ui.ui).unwrap(), w.get_h(&window_ui.ui).unwrap()]
+        };
+        let [whitespace_w, line_h] = measure(" ");
+        let char_w = measure("0")[0];
+        let number_attr = |name: &str, default: f64| {
+            element.attr(name).and_then(|value| value.parse::<f64>().ok()).filter(|n| *n > 0.0).unwrap_or(default)
+        };
+
+        let anchor_names = element.anchor_names();
+        let text = self.control_text(node);
+        let focused = self.form_state.focus == Some(node);
+        let shown = if focused { format!("{}|", text) } else { text };
+        let text_w = measure(&shown)[0];
+        let single_line = line_h + CONTROL_PADDING * 2.0;
+        let (w, h) = match kind {
+            ControlKind::Checkbox | ControlKind::Radio => (line_h, line_h),
+            ControlKind::Text | ControlKind::Password => {
+                ((number_attr("size", 20.0) * char_w).max(text_w) + CONTROL_PADDING * 2.0, single_line)
+            },
+            ControlKind::TextArea => {
+                let lines = shown.lines().count().max(1) as f64;
+                ((number_attr("cols", 20.0) * char_w).max(text_w) + CONTROL_PADDING * 2.0,
+                 number_attr("rows", 2.0).max(lines) * line_h * LINE_SPACING + CONTROL_PADDING * 2.0)
+            },
+            _ => (text_w + CONTROL_PADDING * 4.0, single_line),
+        };
+        let (fill, border) = match kind {
+            ControlKind::Submit | ControlKind::Reset | ControlKind::Button | ControlKind::Select => {
+                (color::LIGHT_GREY, color::DARK_GREY)
+            },
+            _ if focused => (color::WHITE, color::BLUE),
+            _ => (color::WHITE, color::DARK_GREY),
+        };
+
+        let state = &mut self.layout_state;
+        if !state.terminal_space && state.x > 13.0 {
+            state.x += whitespace_w;
+        }
+        if state.x + w > self.window_width - 13.0 && state.x > 13.0 {
+            state.y += line_h * LINE_SPACING;
+            state.x = 13.0;
+        }
+        state.add_anchors(anchor_names);
+        self.display_list.push(DisplayListItem::Control(DisplayListControl {
+            x: state.x,
+            y: state.y,
+            w,
+            h,
+            text: shown,
+            font,
+            fill,
+            border,
+            node,
+        }));
+        state.x += w;
+        // a tall control pushes the next line down below it
+        if h > line_h * LINE_SPACING {
+            state.y += h - line_h * LINE_SPACING;
+        }
+        state.terminal_space = false;
+    }
+
+    /// What's shown inside a form control
+    fn control_text(&self, node: petgraph::graph::NodeIndex) -> String {
+        let element = self.layout_state.element(node).unwrap();
+        match element.control_kind().unwrap() {
+            ControlKind::Text | ControlKind::TextArea | ControlKind::Hidden => self.control_value(node),
+            ControlKind::Password => self.control_value(node).chars().map(|_| '•').collect(),
+            ControlKind::Checkbox if self.is_checked(node) => "x".to_string(),
+            ControlKind::Radio if self.is_checked(node) => "•".to_string(),
+            ControlKind::Checkbox | ControlKind::Radio => String::new(),
+            ControlKind::Select => {
+                let options = self.select_options(node);
+                match self.selected_option(node) {
+                    Some(i) => format!("{} ▾", self.layout_state.text_content(options[i]).trim()),
+                    None => "▾".to_string(),
+                }
+            },
+            // a <button> shows what's inside it, an <input> its value
+            _ if element.tag == "button" => {
+                self.layout_state.text_content(node).split_whitespace().collect::<Vec<_>>().join(" ")
+            },
+            ControlKind::Submit => element.attr("value").unwrap_or("Submit").to_string(),
+            ControlKind::Reset => element.attr("value").unwrap_or("Reset").to_string(),
+            ControlKind::Button => element.attr("value").unwrap_or("").to_string(),
+        }
+    }
+
+    /// A text control's current value: what's been typed, or what the page started it with
+    fn control_value(&self, node: petgraph::graph::NodeIndex) -> String {
+        if let Some(value) = self.form_state.values.get(&node) {
+            return value.clone();
+        }
+        let element = self.layout_state.element(node).unwrap();
+        match element.tag.as_str() {
+            "textarea" => {
+                let text = self.layout_state.text_content(node);
+                // a newline straight after the start tag isn't part of the value
+                text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(&text).to_string()
+            },
+            _ => element.attr("value").unwrap_or("").to_string(),
+        }
+    }
+
+    fn is_checked(&self, node: petgraph::graph::NodeIndex) -> bool {
+        match self.form_state.checked.get(&node) {
+            Some(checked) => *checked,
+            None => self.layout_state.element(node).unwrap().attributes.contains_key("checked"),
+        }
+    }
+
+    /// A select's <option> elements, including any inside an <optgroup>
+    fn select_options(&self, node: petgraph::graph::NodeIndex) -> Vec<petgraph::graph::NodeIndex> {
+        self.layout_state.descendants(node).into_iter()
+            .filter(|node| self.layout_state.element(*node).is_some_and(|element| element.tag == "option"))
+            .collect()
+    }
+
+    /// Which option a select is on: the one picked, the one marked selected, or the first
+    fn selected_option(&self, node: petgraph::graph::NodeIndex) -> Option<usize> {
+        let options = self.select_options(node);
+        if options.is_empty() {
+            return None;
+        }
+        if let Some(selected) = self.form_state.selected.get(&node) {
+            return Some(*selected);
+        }
+        let marked = options.iter().position(|option| {
+            self.layout_state.element(*option).unwrap().attributes.contains_key("selected")
+        });
+        Some(marked.unwrap_or(0))
+    }
+
+    /// Handles a click at a point on the page. Clicking a link hands back where it goes.
+    /// Clicking a control focuses, toggles or cycles it, and clicking a submit button
+    /// hands back where the form goes
+    fn click(&mut self, x: f64, y: f64) -> Option<Navigation> {
+        let inside = |item_x: f64, item_y: f64, w: f64, h: f64| {
+            x >= item_x && x <= item_x + w && y >= item_y && y <= item_y + h
+        };
+        let href = self.display_list.iter().find_map(|item| match item {
+            DisplayListItem::Link(link) if inside(link.x, link.y, link.w, link.h) => Some(link.href.clone()),
+            _ => None,
+        });
+        let node = self.display_list.iter().find_map(|item| match item {
+            DisplayListItem::Control(control) if inside(control.x, control.y, control.w, control.h) => {
+                Some(control.node)
+            },
+            _ => None,
+        });
+        self.form_state.focus = None;
+
+        if let Some(href) = href {
+            // an href that isn't a url at all goes nowhere
+            let url = self.url.join(&href).ok()?;
+            return Some(Navigation { url: url.to_string(), post_body: None });
+        }
+        let node = node?;
+
+        match self.layout_state.element(node).and_then(|element| element.control_kind()) {
+            Some(ControlKind::Text) | Some(ControlKind::Password) | Some(ControlKind::TextArea) => {
+                self.form_state.focus = Some(node);
+            },
+            Some(ControlKind::Checkbox) => {
+                let checked = !self.is_checked(node);
+                self.form_state.checked.insert(node, checked);
+            },
+            Some(ControlKind::Radio) => {
+                // checking a radio button unchecks the others in its group
+                let name = self.layout_state.element(node).unwrap().attr("name").map(str::to_string);
+                for other in self.form_controls(self.layout_state.ancestor(node, "form")) {
+                    let other_element = self.layout_state.element(other).unwrap();
+                    if other_element.control_kind() == Some(ControlKind::Radio)
+                        && other_element.attr("name").map(str::to_string) == name {
+                        self.form_state.checked.insert(other, false);
+                    }
+                }
+                self.form_state.checked.insert(node, true);
+            },
+            Some(ControlKind::Select) => {
+                let options = self.select_options(node).len();
+                if let Some(selected) = self.selected_option(node) {
+                    self.form_state.selected.insert(node, (selected + 1) % options);
+                }
+            },
+            Some(ControlKind::Submit) => {
+                let form = self.layout_state.ancestor(node, "form")?;
+                return self.submit_form(form, Some(node));
+            },
+            Some(ControlKind::Reset) => {
+                for control in self.form_controls(self.layout_state.ancestor(node, "form")) {
+                    self.form_state.values.remove(&control);
+                    self.form_state.checked.remove(&control);
+                    self.form_state.selected.remove(&control);
+                }
+            },
+            _ => (),
+        }
+        None
+    }
+
+    /// Types a character into the focused control. Enter in a one line input submits its form
+    fn type_character(&mut self, c: char) -> Option<Navigation> {
+        let node = self.form_state.focus?;
+        let kind = self.layout_state.element(node).and_then(|element| element.control_kind());
+        let mut value = self.control_value(node);
+        match c {
+            // backspace, and delete on the Mac
+            '\u{8}' | '\u{7f}' => {
+                value.pop();
+            },
+            '\r' | '\n' if kind == Some(ControlKind::TextArea) => value.push('\n'),
+            '\r' | '\n' => {
+                let form = self.layout_state.ancestor(node, "form")?;
+                return self.submit_form(form, None);
+            },
+            c if c.is_control() => return None,
+            c => value.push(c),
+        }
+        self.form_state.values.insert(node, value);
+        None
+    }
+
+    /// The controls in a form, in document order. Without a form, the whole page's
+    fn form_controls(&self, form: Option<petgraph::graph::NodeIndex>) -> Vec<petgraph::graph::NodeIndex> {
+        let root = match form.or(self.layout_state.layout_root) {
+            Some(root) => root,
+            None => return Vec::new(),
+        };
+        self.layout_state.descendants(root).into_iter()
+            .filter(|node| self.layout_state.element(*node).is_some_and(|element| element.control_kind().is_some()))
+            .collect()
+    }
+
+    /// Encodes a form's fields as application/x-www-form-urlencoded, and works out where
+    /// they go: into the query string of the action url for GET, or the body for POST.
+    /// submitter is the button that was clicked, the only button that gets sent
+    fn submit_form(&self, form: petgraph::graph::NodeIndex, submitter: Option<petgraph::graph::NodeIndex>)
+                   -> Option<Navigation> {
+        let mut fields = url::form_urlencoded::Serializer::new(String::new());
+        for node in self.form_controls(Some(form)) {
+            let element = self.layout_state.element(node).unwrap();
+            let name = match element.attr("name") {
+                Some(name) if !name.is_empty() && !element.attributes.contains_key("disabled") => name,
+                _ => continue,
+            };
+            let value = match element.control_kind().unwrap() {
+                ControlKind::Text | ControlKind::Password | ControlKind::Hidden => self.control_value(node),
+                // line breaks in a form are always sent as CRLF
+                ControlKind::TextArea => self.control_value(node).replace("\r\n", "\n").replace('\n', "\r\n"),
+                ControlKind::Checkbox | ControlKind::Radio if self.is_checked(node) => {
+                    element.attr("value").unwrap_or("on").to_string()
+                },
+                ControlKind::Checkbox | ControlKind::Radio => continue,
+                ControlKind::Select => match self.selected_option(node) {
+                    Some(i) => {
+                        let option = self.select_options(node)[i];
+                        match self.layout_state.element(option).unwrap().attr("value") {
+                            Some(value) => value.to_string(),
+                            None => self.layout_state.text_content(option).trim().to_string(),
+                        }
+                    },
+                    None => continue,
+                },
+                ControlKind::Submit | ControlKind::Reset | ControlKind::Button if submitter == Some(node) => {
+                    element.attr("value").unwrap_or("").to_string()
+                },
+                ControlKind::Submit | ControlKind::Reset | ControlKind::Button => continue,
+            };
+            fields.append_pair(name, &value);
+        }
+        let fields = fields.finish();
+
+        let form_element = self.layout_state.element(form).unwrap();
+        // an empty or missing action sends the form back to the page it's on
+        let mut action = match form_element.attr("action") {
+            Some(action) if !action.is_empty() => self.url.join(action).ok()?,
+            _ => self.url.clone(),
+        };
+        action.set_fragment(None);
+        if form_element.attr("method").is_some_and(|method| method.eq_ignore_ascii_case("post")) {
+            Some(Navigation { url: action.to_string(), post_body: Some(fields.into_bytes()) })
+        } else {
+            action.set_query(Some(&fields));
+            Some(Navigation { url: action.to_string(), post_body: None })
+        }
+    }
+
+    /// Lays the current page out again from the top, after the window or a control changed size
+    fn relayout(&mut self, window_ui: &mut WindowUi) {
+        self.display_list.clear();
+        match self.view_source.take() {
+            Some(source) => {
+                self.layout_source(window_ui, &source);
+                self.view_source = Some(source);
+            },
+            None => {
+                self.layout_state.restart();
+                if let Some(root) = self.layout_state.layout_root {
+                    self.layout(window_ui, root);
+                }
+            },
+        }
+    }
+
+    /// Lays out raw page source for view-source:. Each line of source starts a new line
+    /// on screen, and long ones wrap at the window edge. It's all in the monospace font,
+    /// so positions come straight from the column number
+    fn layout_source(&mut self, window_ui: &mut WindowUi, source: &str) {
+        let w = widget::Text::new("M")
+            .font_id(window_ui.font_mono)
+            .font_size(FONT_SIZE);
+        let char_w = w.get_w(&window_ui.ui).unwrap();
+        let line_h = w.get_h(&window_ui.ui).unwrap() * LINE_SPACING;
+        let columns = (((self.window_width - 26.0) / char_w) as usize).max(1);
+
+        let mut column = 0;
+        let mut y = 13.0;
+        // runs of same-colored, non-space characters become one display list item each
+        let mut word = String::new();
+        let mut word_column = 0;
+        let mut word_color = color::BLACK;
+        let mut items = Vec::new();
+        let mut flush = |word: &mut String, word_column: usize, y: f64, color: conrod_core::color::Color| {
+            if !word.is_empty() {
+                items.push(DisplayListItem::Text(DisplayListText {
+                    x: 13.0 + word_column as f64 * char_w,
+                    y,
+                    text: word.clone(),
+                    font: window_ui.font_mono,
+                    color,
+                    underline: false
+                }));
+                word.clear();
+            }
+        };
+
+        for (c, c_color) in self.highlight_source(source) {
+            if c == '\r' {
+                continue;
+            }
+            if c == '\n' || column >= columns {
+                flush(&mut word, word_column, y, word_color);
+                column = 0;
+                y += line_h;
+                if c == '\n' {
+                    continue;
+                }
+            }
+            if c == '\t' {
+                flush(&mut word, word_column, y, word_color);
+                column = (column / 8 + 1) * 8;
+            } else if c.is_whitespace() {
+                flush(&mut word, word_column, y, word_color);
+                column += 1;
+            } else {
+                if c_color != word_color {
+                    flush(&mut word, word_column, y, word_color);
+                }
+                if word.is_empty() {
+                    word_column = column;
+                    word_color = c_color;
+                }
+                word.push(c);
+                column += 1;
+            }
+        }
+        flush(&mut word, word_column, y, word_color);
+        self.display_list.extend(items);
+    }
+
+    /// Pairs each character of the source with the color to show it in: tags in blue,
+    /// quoted attribute values in red, comments in green and everything else black
+    fn highlight_source(&self, source: &str) -> Vec<(char, conrod_core::color::Color)> {
+        let mut highlighted = Vec::new();
+        let mut in_tag = false;
+        let mut in_comment = false;
+        let mut quote: Option<char> = None;
+
+        let chars: Vec<char> = source.chars().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            if in_comment {
+                highlighted.push((c, color::DARK_GREEN));
+                if c == '>' && i >= 2 && chars[i - 2..i] == ['-', '-'] {
+                    in_comment = false;
+                }
+            } else if in_tag {
+                match quote {
+                    Some(q) => {
+                        highlighted.push((c, color::DARK_RED));
+                        if c == q {
+                            quote = None;
+                        }
+                    },
+                    None => {
+                        if c == '"' || c == '\'' {
+                            quote = Some(c);
+                            highlighted.push((c, color::DARK_RED));
+                        } else {
+                            highlighted.push((c, color::DARK_BLUE));
+                            in_tag = c != '>';
+                        }
+                    },
+                }
+            } else if c == '<' && chars[i..].starts_with(&['<', '!', '-', '-']) {
+                in_comment = true;
+                highlighted.push((c, color::DARK_GREEN));
+            } else if c == '<' && chars.get(i + 1).is_some_and(|n| n.is_alphabetic() || ['/', '!', '?'].contains(n)) {
+                in_tag = true;
+                highlighted.push((c, color::DARK_BLUE));
+            } else {
+                highlighted.push((c, color::BLACK));
+            }
+        }
+        highlighted
+    }
+
     fn set_up_window(&self) -> WindowUi {
         let mut ui = conrod_core::UiBuilder::new([INIT_WIDTH as f64, INIT_HEIGHT as f64]).build();
         // Build the window.
@@ -625,6 +1590,8 @@ impl Tundra {
         let font_i = ui.fonts.insert_from_file(font_path).unwrap();
         let font_path = assets.join("fonts/NotoSans/NotoSans-BoldItalic.ttf");
         let font_bi = ui.fonts.insert_from_file(font_path).unwrap();
+        let font_path = assets.join("fonts/DejaVuSansMono/DejaVuSansMono.ttf");
+        let font_mono = ui.fonts.insert_from_file(font_path).unwrap();
 //        let font_path = assets.join("fonts/PingFang-Regular.ttf");
 //        ui.fonts.insert_from_file(font_path).unwrap();
 
@@ -636,10 +1603,11 @@ impl Tundra {
             font_b,
             font_i,
             font_bi,
+            font_mono,
         }
     }
 
-    fn render(&mut self, mut window_ui: &mut WindowUi) {
+    fn render(&mut self, window_ui: &mut WindowUi) {
         // A type used for converting `conrod_core::render::Primitives` into `Command`s that can be used
         // for drawing to the glium `Surface`.
         let mut renderer = conrod_glium::Renderer::new(&window_ui.display.0).unwrap();
@@ -652,7 +1620,12 @@ impl Tundra {
 
         // Poll events from the window.
         let mut event_loop = support::EventLoop::new();
+        // where the mouse is, in window coordinates
+        let mut mouse = (0.0, 0.0);
         'main: loop {
+            let mut navigation = None;
+            let mut history_step = None;
+            let mut needs_layout = false;
 
             // Handle all events.
             for event in event_loop.next(&mut window_ui.events_loop) {
@@ -694,12 +1667,62 @@ impl Tundra {
                             },
                             ..
                         } => self.scroll_up(),
+                        glium::glutin::WindowEvent::KeyboardInput {
+                            input: glium::glutin::KeyboardInput {
+                                virtual_keycode: Some(glium::glutin::VirtualKeyCode::Left),
+                                state: glium::glutin::ElementState::Pressed,
+                                modifiers: glium::glutin::ModifiersState { alt: true, .. },
+                                ..
+                            },
+                            ..
+                        } => history_step = Some(HistoryStep::Back),
+                        glium::glutin::WindowEvent::KeyboardInput {
+                            input: glium::glutin::KeyboardInput {
+                                virtual_keycode: Some(glium::glutin::VirtualKeyCode::Right),
+                                state: glium::glutin::ElementState::Pressed,
+                                modifiers: glium::glutin::ModifiersState { alt: true, .. },
+                                ..
+                            },
+                            ..
+                        } => history_step = Some(HistoryStep::Forward),
+                        // backspace only goes back when it isn't deleting from a text box
+                        glium::glutin::WindowEvent::KeyboardInput {
+                            input: glium::glutin::KeyboardInput {
+                                virtual_keycode: Some(glium::glutin::VirtualKeyCode::Back),
+                                state: glium::glutin::ElementState::Pressed,
+                                ..
+                            },
+                            ..
+                        } if self.form_state.focus.is_none() => history_step = Some(HistoryStep::Back),
+                        glium::glutin::WindowEvent::CursorMoved { position, .. } => {
+                            mouse = (position.x, position.y);
+                        },
+                        glium::glutin::WindowEvent::MouseInput {
+                            state: glium::glutin::ElementState::Pressed,
+                            button: glium::glutin::MouseButton::Left,
+                            ..
+                        } => {
+                            navigation = self.click(mouse.0, mouse.1 + self.scroll_y);
+                            needs_layout = true;
+                        },
+                        glium::glutin::WindowEvent::ReceivedCharacter(c) => {
+                            navigation = self.type_character(c);
+                            needs_layout = true;
+                        },
                         _ => (),
                     },
                     _ => (),
                 }
             } //...end events loop
 
+            if let Some(step) = history_step {
+                self.step_history(window_ui, step);
+            } else if let Some(navigation) = navigation {
+                self.navigate(window_ui, navigation);
+            } else if needs_layout {
+                self.relayout(window_ui);
+            }
+
             // Instantiate all widgets in the GUI.
             self.set_text(window_ui.ui.set_widgets(), ids);
 
@@ -716,9 +1739,7 @@ impl Tundra {
             if window_ui.ui.win_w != self.window_width || window_ui.ui.win_h != self.window_height {
                 self.window_width = window_ui.ui.win_w;
                 self.window_height = window_ui.ui.win_h;
-                // clear the display list, especially when re-laying out
-                self.display_list.clear();
-                self.layout(&mut window_ui, self.layout_state.layout_root.unwrap());
+                self.relayout(window_ui);
 //                window_ui.ui.needs_redraw();
             }
         } //...end draw loop
@@ -737,6 +1758,7 @@ impl Tundra {
         //We could be more memory efficient by only taking up space we need, but eh
         ids.text.resize(self.display_list.len(), &mut ui.widget_id_generator());
         ids.underlines.resize(self.display_list.len(), &mut ui.widget_id_generator());
+        ids.controls.resize(self.display_list.len(), &mut ui.widget_id_generator());
 //        ids.rectangles.resize(self.display_list.len(), &mut ui.widget_id_generator());
 
         //manual loop because I can't figure out how to borrow the display_list text
@@ -779,6 +1801,33 @@ impl Tundra {
                         //let r = widget::BorderedRectangle::new(w_wh).xy(rel_pos).color(color::TRANSPARENT).set(ids.rectangles[i], ui);
                     }
                 },
+                // nothing to draw, the link's text already is
+                DisplayListItem::Link(_) => (),
+                DisplayListItem::Control(control) => {
+                    let (x, y, fill, border, font) = (control.x, control.y, control.fill, control.border, control.font);
+                    let wh = [control.w, control.h];
+                    let text = &control.text.clone();
+
+                    if y + wh[1] > self.scroll_y && y < self.scroll_y + self.window_height {
+                        let rel_pos = self.rel(ui, wh, [x, y - self.scroll_y]);
+                        widget::BorderedRectangle::new(wh)
+                            .xy(rel_pos)
+                            .color(fill)
+                            .border(1.0)
+                            .border_color(border)
+                            .set(ids.controls[i], ui);
+
+                        let w = widget::Text::new(text)
+                            .color(color::BLACK)
+                            .font_id(font)
+                            .font_size(FONT_SIZE)
+                            .line_spacing(LINE_SPACING);
+                        let w_wh = w.get_wh(ui).unwrap();
+                        let text_pos = [x + CONTROL_PADDING, y + CONTROL_PADDING - self.scroll_y];
+                        let rel_pos = self.rel(ui, w_wh, text_pos);
+                        w.xy(rel_pos).set(ids.text[i], ui);
+                    }
+                },
             }
         }
     }
@@ -810,16 +1859,10 @@ impl Tundra {
     fn scroll_down(&mut self) {
         self.scroll_y += SCROLL_STEP;
 
-        // Don't scroll past the bottom of the page
-        let last_item = self.display_list.last().unwrap();
-        let last_y;
-        match last_item {
-            DisplayListItem::Text(item) => last_y = item.y,
-        };
-
-        if self.scroll_y > last_y - self.window_height as f64 {
-            self.scroll_y = last_y - self.window_height as f64;
-        }
+        // Don't scroll past the bottom of the page, and a page with nothing on it
+        // (about:blank) doesn't scroll at all
+        let bottom = self.display_list.last().map_or(0.0, |item| item.y() - self.window_height);
+        self.scroll_y = self.scroll_y.min(bottom).max(0.0);
     }
 
     fn scroll_up(&mut self) {
@@ -841,6 +1884,7 @@ widget_ids! {
             oval,
             text[],
             underlines[],
+            controls[],
             dummy_text, //for use in laying out text
             rectangles[],
         }